@@ -1,8 +1,13 @@
-use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use std::collections::HashMap;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 
-use crate::{api::jobs::JobResponse, config::Config, CONTEST_LIST, JOB_LIST, USER_LIST};
+use crate::config::{Config, JudgingPolicy};
+use crate::{store, CONTEST_LIST, JOB_LIST, USER_LIST};
 
+use super::error::ApiError;
+use super::jobs::{JobResponse, JudgeResult, State};
 use super::users::User;
 
 #[get("/contests/{contestid}/ranklist")]
@@ -10,15 +15,11 @@ async fn get_ranklist(
     config: web::Data<Config>,
     req: HttpRequest,
     contestid: web::Path<usize>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     println!("1");
     let info = match web::Query::<RankInfo>::from_query(req.query_string()) {
         Err(_) => {
-            return HttpResponse::BadRequest().json(Job {
-                code: 1,
-                reason: "ERR_INVALID_ARGUMENT".to_string(),
-                message: "Invalid argument".to_string(),
-            });
+            return Err(ApiError::invalid_argument("Invalid argument"));
         }
         Ok(info) => info,
     };
@@ -34,16 +35,16 @@ async fn get_ranklist(
         problem_ids: Vec::new(),
         user_ids: Vec::new(),
         submission_limit: 100,
+        judging_policy: None,
     };
     if contest_id != 0 {
         let clock = CONTEST_LIST.lock().unwrap();
         let cindex = clock.iter().position(|x| x.id == Some(contest_id));
         if cindex.is_none() {
-            return HttpResponse::NotFound().json(Job {
-                code: 3,
-                reason: "ERR_NOT_FOUND".to_string(),
-                message: "Contest {contest_id} not found.".to_string(),
-            });
+            return Err(ApiError::not_found(format!(
+                "Contest {} not found.",
+                contest_id
+            )));
         }
         let cindex = cindex.unwrap();
         contest = clock[cindex].clone();
@@ -92,19 +93,18 @@ async fn get_ranklist(
     // id 不为0， 只遍历当前contest的测评
     let lock = JOB_LIST.lock().unwrap();
 
-    let mut vec = if contest_id == 0 {
-        lock.clone()
-    } else {
-        lock.clone()
-            .into_iter()
-            .filter(|x| x.submission.contest_id as usize == contest_id)
-            .collect()
-    };
+    // 只有评测完成的任务计入排行榜：排队中、正在评测（包括重新评测）和被取消的任务都不计入
+    let mut vec: Vec<JobResponse> = lock
+        .iter()
+        .filter(|x| contest_id == 0 || x.submission.contest_id as usize == contest_id)
+        .filter(|x| x.state == State::Finished)
+        .cloned()
+        .collect();
 
     println!("6");
 
     // 按problem id 排序
-    vec.sort_by(|a, b| a.submission.problem_id.cmp(&b.submission.problem_id));
+    vec.sort_by_key(|a| a.submission.problem_id);
 
     for item in vec.iter() {
         let user_id = item.submission.user_id;
@@ -137,6 +137,58 @@ async fn get_ranklist(
         }
     }
     drop(lock);
+
+    // 动态排名：每个测试点的竞争得分 = 分数 * dynamic_ranking_ratio * 最短用时 / 用时，
+    // 用时取每个用户该题最后一次通过的提交
+    for problem in config.problems.iter() {
+        if problem.typ != "dynamic_ranking" {
+            continue;
+        }
+        let problem_index = if contest_id == 0 {
+            problem.id as usize
+        } else {
+            match contest
+                .problem_ids
+                .iter()
+                .position(|a| a == &(problem.id as usize))
+            {
+                Some(index) => index,
+                None => continue,
+            }
+        };
+        if problem_index >= problems_num {
+            continue;
+        }
+        let ratio = problem.misc.dynamic_ranking_ratio.unwrap_or(0.0);
+
+        // user_id -> 该题最后一次通过的提交，vec 中同一题的提交按创建顺序排列
+        let mut accepted: HashMap<u32, &JobResponse> = HashMap::new();
+        for item in vec.iter() {
+            if item.submission.problem_id == problem.id && item.result == JudgeResult::Accepted {
+                accepted.insert(item.submission.user_id, item);
+            }
+        }
+
+        for (i, case) in problem.cases.iter().enumerate() {
+            // 按墙上时间比较，等待（如 sleep）的时间也计入运行时间
+            let case_time = |job: &JobResponse| job.cases.get(i + 1).map(|x| x.wall_time.max(1));
+            let min_time = match accepted.values().filter_map(|job| case_time(job)).min() {
+                Some(min_time) => min_time,
+                None => continue,
+            };
+            for rank in rank_list.iter_mut() {
+                let time = rank
+                    .user
+                    .id
+                    .and_then(|id| accepted.get(&id))
+                    .and_then(|job| case_time(job));
+                if let Some(time) = time {
+                    rank.scores[problem_index] +=
+                        case.score * ratio * min_time as f64 / time as f64;
+                }
+            }
+        }
+    }
     println!("7");
 
     // rank list scores and users updated
@@ -208,13 +260,13 @@ async fn get_ranklist(
 
     println!("8");
 
-    HttpResponse::Ok().json(rank_list)
+    Ok(HttpResponse::Ok().json(rank_list))
 
     // "s"
 }
 
 #[post("/contests")]
-async fn post_contests(mut contest: web::Json<Contest>) -> impl Responder {
+async fn post_contests(mut contest: web::Json<Contest>) -> Result<HttpResponse, ApiError> {
     // 检查id 字段是否存在
     if contest.id.is_none() {
         // id 字段不存在，新建比赛并返回比赛信息作为响应
@@ -222,42 +274,38 @@ async fn post_contests(mut contest: web::Json<Contest>) -> impl Responder {
         let mut lock = CONTEST_LIST.lock().unwrap();
         contest.id = Some(lock.len() + 1);
         lock.push(contest.clone());
+        store::save_contest(&contest);
     }
 
-    HttpResponse::Ok().json(contest)
+    Ok(HttpResponse::Ok().json(contest))
 }
 
 #[get("/contests")]
-async fn get_contests() -> impl Responder {
+async fn get_contests() -> Result<HttpResponse, ApiError> {
     let lock = CONTEST_LIST.lock().unwrap();
-    HttpResponse::Ok().json(&*lock)
+    Ok(HttpResponse::Ok().json(&*lock))
 }
 
 #[get("/contests/{contestid}")]
-async fn get_contests_by_id(contestid: web::Path<String>) -> impl Responder {
+async fn get_contests_by_id(contestid: web::Path<String>) -> Result<HttpResponse, ApiError> {
     let id = match contestid.to_string().parse::<usize>() {
         Err(_) => {
-            return HttpResponse::BadRequest().json(Job {
-                reason: "ERR_NOT_FOUND".to_string(),
-                code: 3,
-                message: format!("Contest {} not found.", contestid),
-            })
+            return Err(ApiError::not_found(format!(
+                "Contest {} not found.",
+                contestid
+            )))
         }
         Ok(id) => id,
     };
 
     let lock = CONTEST_LIST.lock().unwrap();
-    let index = lock.iter().position(|x| x.id.unwrap() == id);
-    if index.is_none() {
+    match lock.iter().position(|x| x.id.unwrap() == id) {
         // 找不到比赛
-        return HttpResponse::NotFound().json(Job {
-            reason: "ERR_NOT_FOUND".to_string(),
-            code: 3,
-            message: format!("Contest {} not found.", contestid),
-        });
-    } else {
-        let index = index.unwrap();
-        return HttpResponse::Ok().json(&lock[index]);
+        None => Err(ApiError::not_found(format!(
+            "Contest {} not found.",
+            contestid
+        ))),
+        Some(index) => Ok(HttpResponse::Ok().json(&lock[index])),
     }
 }
 
@@ -270,6 +318,9 @@ pub struct Contest {
     pub problem_ids: Vec<usize>,
     pub user_ids: Vec<usize>,
     pub submission_limit: i32,
+    /// 比赛中所有题目的评测策略，缺省时使用题目的评测策略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judging_policy: Option<JudgingPolicy>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -293,9 +344,3 @@ struct RankInfo {
     scoring_rule: Option<String>,
     tie_breaker: Option<String>,
 }
-#[derive(Serialize)]
-struct Job {
-    code: u32,
-    reason: String,
-    message: String,
-}
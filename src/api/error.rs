@@ -0,0 +1,176 @@
+//! api 共用的错误类型，以及把处理请求时的 panic 转换为 `ERR_INTERNAL` 的中间件。
+
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+
+/// 错误类型，对应响应中的 `code` 与 `reason`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    InvalidArgument,
+    InvalidState,
+    NotFound,
+    RateLimit,
+    #[allow(dead_code)]
+    External,
+    Internal,
+}
+
+impl ErrorKind {
+    fn code(self) -> u32 {
+        match self {
+            ErrorKind::InvalidArgument => 1,
+            ErrorKind::InvalidState => 2,
+            ErrorKind::NotFound => 3,
+            ErrorKind::RateLimit => 4,
+            ErrorKind::External => 5,
+            ErrorKind::Internal => 6,
+        }
+    }
+
+    fn reason(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidArgument => "ERR_INVALID_ARGUMENT",
+            ErrorKind::InvalidState => "ERR_INVALID_STATE",
+            ErrorKind::NotFound => "ERR_NOT_FOUND",
+            ErrorKind::RateLimit => "ERR_RATE_LIMIT",
+            ErrorKind::External => "ERR_EXTERNAL",
+            ErrorKind::Internal => "ERR_INTERNAL",
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            ErrorKind::InvalidArgument | ErrorKind::InvalidState | ErrorKind::RateLimit => {
+                StatusCode::BAD_REQUEST
+            }
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::External | ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// api 返回的错误，响应形如 `{"code": 3, "reason": "ERR_NOT_FOUND", "message": "Job 1 not found."}`
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: u32,
+    reason: &'a str,
+    message: &'a str,
+}
+
+impl ApiError {
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        ApiError {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_argument<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::InvalidArgument, message)
+    }
+
+    pub fn invalid_state<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::InvalidState, message)
+    }
+
+    pub fn not_found<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn rate_limit<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::RateLimit, message)
+    }
+
+    pub fn internal<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.reason(), self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.kind.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.kind.code(),
+            reason: self.kind.reason(),
+            message: &self.message,
+        })
+    }
+}
+
+/// 中间件（通过 `App::wrap_fn` 使用）：处理请求时发生 panic 时返回 `ERR_INTERNAL`，而不是直接断开连接。
+///
+/// 返回的错误由 actix-web 通过 `ApiError::error_response` 转换为响应。
+pub fn catch_panic<S, B>(
+    req: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    // 不能克隆 HttpRequest：路由时需要独占它
+    let path = req.path().to_string();
+    let future = panic::catch_unwind(AssertUnwindSafe(|| service.call(req)));
+    async move {
+        let result = match future {
+            Ok(future) => CatchUnwind(Box::pin(future)).await,
+            Err(payload) => Err(payload),
+        };
+        result.unwrap_or_else(|payload| {
+            let message = panic_message(&payload);
+            log::error!("panicked while handling {}: {}", path, message);
+            Err(ApiError::internal(message).into())
+        })
+    }
+}
+
+/// 轮询时捕获 panic 的 future
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "internal error".to_string()
+    }
+}
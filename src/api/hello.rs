@@ -12,5 +12,5 @@ pub async fn greet(name: web::Path<String>) -> impl Responder {
 async fn exit() -> impl Responder {
     log::info!("Shutdown as requested");
     std::process::exit(0);
-    format!("Exited")
+    "Exited".to_string()
 }
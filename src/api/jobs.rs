@@ -1,16 +1,24 @@
-use std::fs;
-use std::io::prelude::*;
-use std::process::Stdio;
-use std::time::{Duration, Instant};
-use std::{fs::File, path::Path, process::Command, sync::Mutex};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
-use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
-use crate::{CONTEST_LIST, JOB_LIST, USER_LIST};
-use chrono::Utc;
-use wait_timeout::ChildExt;
+use crate::judge::{self, Judgement};
+use crate::queue::JobQueue;
+use crate::{store, CONTEST_LIST, JOB_LIST, USER_LIST};
+use chrono::{DateTime, Utc};
+
+use super::error::ApiError;
+
+lazy_static! {
+    /// 正在评测的任务 id -> 取消标志，`DELETE /jobs/{jobid}` 通过它杀死正在运行的程序
+    static ref CANCEL_FLAGS: Mutex<HashMap<i32, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct PostJob {
@@ -32,26 +40,45 @@ struct GetJob {
     to: Option<String>,
     state: Option<State>,
     result: Option<JudgeResult>,
+    /// 排序依据，默认为创建时间
+    sort: Option<SortKey>,
+    /// 排序方向，默认为升序
+    order: Option<Order>,
+    /// 跳过的测评数
+    offset: Option<usize>,
+    /// 最多返回的测评数
+    limit: Option<usize>,
+    /// 为 false 时不返回源代码
+    source_code: Option<bool>,
 }
 
-#[derive(Serialize)]
-struct Job {
-    code: u32,
-    reason: String,
-    message: String,
+#[derive(Debug, Deserialize, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SortKey {
+    Id,
+    CreatedTime,
+    Score,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Order {
+    Asc,
+    Desc,
 }
 
 pub struct JobCounter {
     pub counter: Mutex<i32>,
 }
 
-/// body: 请求的正文, config: 配置信息
+/// body: 请求的正文, config: 配置信息, queue: 评测队列
 #[post("/jobs")]
 async fn post_jobs(
     body: web::Json<PostJob>,
     config: web::Data<Config>,
     counter: web::Data<JobCounter>,
-) -> impl Responder {
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
     // # 检查请求的合法性
 
     // 检查编程语言是否在配置中, 检查题目 ID 是否在配置中
@@ -64,21 +91,13 @@ async fn post_jobs(
     if !config_languages.iter().any(|x| &x.name == current_language)
         || !config_problems.iter().any(|x| &x.id == current_problem_id)
     {
-        return HttpResponse::NotFound().json(Job {
-            code: 3,
-            reason: "ERR_NOT_FOUND".to_string(),
-            message: "HTTP 404 Not Found".to_string(),
-        });
+        return Err(ApiError::not_found("HTTP 404 Not Found"));
     }
     println!("1");
     // 检查用户 ID 是否存在
     let lock = USER_LIST.lock().unwrap();
     if !lock.iter().any(|x| x.id.unwrap() == body.user_id) {
-        return HttpResponse::NotFound().json(Job {
-            code: 3,
-            reason: "ERR_NOT_FOUND".to_string(),
-            message: "HTTP 404 Not Found".to_string(),
-        });
+        return Err(ApiError::not_found("HTTP 404 Not Found"));
     }
     drop(lock);
     println!("2");
@@ -91,32 +110,20 @@ async fn post_jobs(
             .iter()
             .position(|x| x.id == Some(body.contest_id as usize));
         if contest_index.is_none() {
-            return HttpResponse::NotFound().json(Job {
-                code: 3,
-                reason: "ERR_NOT_FOUND".to_string(),
-                message: "HTTP 404 Not Found".to_string(),
-            });
+            return Err(ApiError::not_found("HTTP 404 Not Found"));
         }
         println!("3");
 
         // 检查用户 ID 是否在此比赛中
         let contest = lock[contest_index.unwrap()].clone();
         if !contest.user_ids.contains(&(body.user_id as usize)) {
-            return HttpResponse::BadRequest().json(Job {
-                code: 1,
-                reason: "ERR_INVALID_ARGUMENT".to_string(),
-                message: "HTTP 400 Bad Request".to_string(),
-            });
+            return Err(ApiError::invalid_argument("HTTP 400 Bad Request"));
         }
         println!("4");
 
         // 检查题目ID是否在此比赛中
         if !contest.problem_ids.contains(&(body.problem_id as usize)) {
-            return HttpResponse::BadRequest().json(Job {
-                code: 1,
-                reason: "ERR_INVALID_ARGUMENT".to_string(),
-                message: "HTTP 400 Bad Request".to_string(),
-            });
+            return Err(ApiError::invalid_argument("HTTP 400 Bad Request"));
         }
         drop(lock);
         println!("5");
@@ -129,13 +136,10 @@ async fn post_jobs(
             .filter(|x| x.submission.user_id == body.user_id)
             .filter(|x| x.submission.problem_id == body.problem_id)
             .filter(|x| x.submission.contest_id == body.contest_id)
+            .filter(|x| x.state != State::Canceled)
             .collect();
         if (v.len() as i32) >= contest.submission_limit {
-            return HttpResponse::BadRequest().json(Job {
-                code: 4,
-                reason: "ERR_RATE_LIMIT".to_string(),
-                message: "HTTP 400 Bad Request".to_string(),
-            });
+            return Err(ApiError::rate_limit("HTTP 400 Bad Request"));
         }
         drop(lock);
         println!("6");
@@ -143,561 +147,406 @@ async fn post_jobs(
 
     // ^ 请求合法
 
-    // # 实现阻塞评测（在评测结束时发送响应）
+    // # 实现非阻塞评测（将任务加入评测队列后立即返回）
 
     // 维护测评id
     let mut cnt = counter.counter.lock().unwrap();
     *cnt += 1;
     let id = *cnt;
+    drop(cnt);
 
     // 维护测评time stamp
-    let created_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+    let created_time = now();
     let updated_time = created_time.clone();
 
+    // 测评前所有测试点（含编译）均为等待状态
+    let problem = config_problems
+        .iter()
+        .find(|&x| &x.id == current_problem_id)
+        .unwrap();
+    let cases = Judgement::waiting(problem).cases;
+
     // 维护当前测评的请求json
-    let submission = body.to_owned();
+    let submission = body.into_inner();
+
+    let res = JobResponse {
+        id,
+        created_time,
+        updated_time,
+        submission,
+        state: State::Queueing,
+        result: JudgeResult::Waiting,
+        score: 0.0,
+        cases,
+    };
+    let mut lock = JOB_LIST.lock().unwrap();
+    lock.push(res.clone());
+    store::save_job(&res);
+    drop(lock);
 
-    // 维护测评状态的变量
-    let mut state = State::Queueing; // 整体测评状态
-    let mut job_result = JudgeResult::Waiting; // 整体测评结果
-    let mut compilation_result = JudgeResult::Waiting;
-    let mut case_result = JudgeResult::Waiting; // 当前测试点测评结果
+    if queue.push(id).is_err() {
+        fail_job(id, "judge workers have exited".to_string());
+        return Err(ApiError::internal("Judge workers have exited."));
+    }
+    Ok(HttpResponse::Ok().json(res))
+}
 
-    // # 编译
+/// 评测线程调用：评测 id 对应的任务，并在评测过程中实时更新 JOB_LIST 中的状态
+pub fn judge_job(id: i32, config: &Config) {
+    let lock = JOB_LIST.lock().unwrap();
+    let submission = match lock.iter().find(|x| x.id == id) {
+        Some(job) => job.submission.clone(),
+        None => return,
+    };
+    drop(lock);
 
     // 维护当前测评的语言配置 language
-    let mut language_iter = config_languages.iter();
-    let language = language_iter
-        .find(|&x| &x.name == current_language)
-        .unwrap();
+    let language = config
+        .languages
+        .iter()
+        .find(|&x| x.name == submission.language);
 
     // 维护当前测评对应的题目 problem
-    let mut problem_iter = config_problems.iter();
-    let problem = problem_iter.find(|&x| &x.id == current_problem_id).unwrap();
-
-    // 创建临时测评目录  e.g. TMPDIR/0/
-    let temp_dir = Path::new("TMPDIR").join(id.to_string());
-    fs::create_dir_all(&temp_dir).unwrap();
-
-    // # 创建源代码文件  e.g. main.rs
-    let src_file = temp_dir.join(&language.file_name);
-
-    let display = src_file.display();
-    let mut file = match File::create(&src_file) {
-        Err(why) => panic!("couldn't create {}: {:?}", display, why),
-        Ok(file) => file,
+    let problem = config
+        .problems
+        .iter()
+        .find(|&x| x.id == submission.problem_id);
+
+    // 重启后配置中可能已经没有该任务的语言或题目
+    let (language, problem) = match (language, problem) {
+        (Some(language), Some(problem)) => (language, problem),
+        (None, _) => {
+            let info = format!("language {} not found", submission.language);
+            return fail_job(id, info);
+        }
+        (_, None) => {
+            let info = format!("problem {} not found", submission.problem_id);
+            return fail_job(id, info);
+        }
     };
 
-    match file.write_all(submission.source_code.as_bytes()) {
-        Err(why) => {
-            panic!("couldn't write to {}: {:?}", display, why)
-        }
-        Ok(_) => println!("successfully wrote to {}", display),
+    // 比赛的评测策略优先于题目的评测策略
+    let contest_policy = CONTEST_LIST
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|x| x.id == Some(submission.contest_id as usize))
+        .and_then(|x| x.judging_policy);
+    let mut problem = problem.clone();
+    if contest_policy.is_some() {
+        problem.misc.judging_policy = contest_policy;
     }
-    // ^ 源码写入完毕
 
-    // # 根据编程语言配置，将源代码编译成可执行文件 e.g. main
-
-    // 更新测评状态
-    state = State::Running;
-    job_result = JudgeResult::Running;
-    compilation_result = JudgeResult::Running;
-
-    // get language-specific commands
-    let commands = &language.command;
-    let commands: Vec<String> = commands
-        .iter()
-        .map(|x| {
-            if x == "%OUTPUT%" {
-                temp_dir.join("main").to_str().unwrap().to_string()
-            } else if x == "%INPUT%" {
-                src_file.to_str().unwrap().to_string()
-            } else {
-                x.to_string()
-            }
-        })
-        .collect();
-    eprintln!("commands = {:?}", commands);
+    // 临时测评目录  e.g. TMPDIR/0/
+    let work_dir = Path::new("TMPDIR").join(id.to_string());
 
-    // 子进程：编译
-    let status = Command::new(&commands[0])
-        .args(&commands[1..])
-        .status()
-        .unwrap();
-    // ^ 编译完成
-
-    // 错误处理：编译
-    // 编译成功
-    if status.code().unwrap() == 0 {
-        compilation_result = JudgeResult::CompilationSuccess;
-    } else {
-        // 编译失败
-        compilation_result = JudgeResult::CompilationError;
-        job_result = JudgeResult::CompilationError;
-        state = State::Finished;
+    // 只评测仍在排队的任务，排队时被取消的任务直接跳过
+    let cancel = Arc::new(AtomicBool::new(false));
+    let mut lock = JOB_LIST.lock().unwrap();
+    match lock.iter_mut().find(|x| x.id == id) {
+        Some(job) if job.state == State::Queueing => {
+            job.state = State::Running;
+            job.updated_time = now();
+            store::save_job(job);
+            CANCEL_FLAGS.lock().unwrap().insert(id, Arc::clone(&cancel));
+        }
+        _ => return,
     }
+    drop(lock);
 
-    // #按照顺序对数据点进行评测
-
-    // 维护测试点信息
-    let problem_cases = &problem.cases;
-
-    // 维护测试结果相关信息
-    let mut score = 0.0;
-    let mut test_case_id = 0;
-    let mut test_cases: Vec<Case> = Vec::new();
-    let mut time = 0;
-    let memory = 0;
-    let info = "".to_string();
-
-    // case 0: 编译
-    test_cases.push(Case {
-        id: 0,
-        result: compilation_result,
-        time: 0,
-        memory,
-        info: info.clone(),
+    let judgement = judge::judge(
+        &submission,
+        &problem,
+        language,
+        &work_dir,
+        &config.judge,
+        &cancel,
+        |judgement| update_job(id, |job| job.apply(judgement)),
+    );
+    update_job(id, |job| {
+        CANCEL_FLAGS.lock().unwrap().remove(&id);
+        job.apply(&judgement);
+        job.state = if cancel.load(Ordering::Relaxed) {
+            State::Canceled
+        } else {
+            State::Finished
+        };
     });
+}
 
-    // other cases
-    for (i, problem_case) in problem_cases.iter().enumerate() {
-        let index = i + 1;
-        test_case_id += 1;
-
-        if job_result != JudgeResult::CompilationError {
-            // 指定in out文件
-            let in_file = File::open(&problem_case.input_file).unwrap();
-            let out_file = File::create(temp_dir.join("test.out")).unwrap();
-
-            // 维护计时器s
-            let now = Instant::now();
-
-            let mut child = Command::new(temp_dir.join("main"))
-                .stdin(Stdio::from(in_file))
-                .stdout(Stdio::from(out_file))
-                .stderr(Stdio::null())
-                .spawn()
-                .unwrap();
-            let time_limit = problem_case.time_limit;
-            let status_code = match child
-                .wait_timeout(Duration::from_micros(time_limit))
-                .unwrap()
-            {
-                Some(status) => status.code(),
-                None => {
-                    // child hasn't exited yet
-                    child.kill().unwrap();
-                    child.wait().unwrap().code()
-                }
-            };
-
-            time = now.elapsed().as_micros() as u64;
-
-            // 错误处理: 程序运行返回值
-            if status_code == Some(101) {
-                case_result = JudgeResult::RuntimeError;
-                job_result = case_result;
-            } else if status_code == Some(0) {
-                // # 运行成功，比较test.out & file.ans
-
-                let out_str = fs::read_to_string(temp_dir.join("test.out")).unwrap();
-                let ans_str = fs::read_to_string(&problem_case.answer_file).unwrap();
-                eprintln!("out_str = {:?}", out_str);
-                eprintln!("ans_str = {:?}", ans_str);
-
-                if problem.typ == "standard" {
-                    let out_str_vec: Vec<String> = out_str
-                        .trim()
-                        .split("\n")
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    let ans_str_vec: Vec<String> = ans_str
-                        .trim()
-                        .split("\n")
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    eprintln!("out_str_vec = {:?}", out_str_vec);
-                    eprintln!("ans_str_vec = {:?}", ans_str_vec);
-                    if out_str_vec == ans_str_vec {
-                        score += &problem_case.score;
-                        case_result = JudgeResult::Accepted;
-                    } else {
-                        case_result = JudgeResult::WrongAnswer;
-                        job_result = JudgeResult::WrongAnswer;
-                    }
-                } else if problem.typ == "strict" {
-                    if out_str == ans_str {
-                        score += &problem_case.score;
-                        case_result = JudgeResult::Accepted;
-                    } else {
-                        case_result = JudgeResult::WrongAnswer;
-                        job_result = JudgeResult::WrongAnswer;
-                    }
-                }
-            } else if status_code == None {
-                // tle
-                case_result = JudgeResult::TimeLimitExceeded;
-                job_result = JudgeResult::TimeLimitExceeded;
-            } else {
-            }
-        } else {
-            case_result = JudgeResult::Waiting;
+/// 评测系统出错，无法完成 id 对应的测评任务：结果为 `System Error`，`info` 记录在编译结果中
+pub fn fail_job(id: i32, info: String) {
+    log::error!("job {} failed: {}", id, info);
+    update_job(id, |job| {
+        CANCEL_FLAGS.lock().unwrap().remove(&id);
+        // 已经取消的任务保持取消状态，不计入排行榜和提交次数
+        if job.state != State::Canceled {
+            job.state = State::Finished;
         }
+        job.result = JudgeResult::SystemError;
+        if let Some(case) = job.cases.first_mut() {
+            case.result = JudgeResult::SystemError;
+            case.info = info;
+        }
+    });
+}
 
-        // 更新test_cases
-        test_cases.push(Case {
-            id: test_case_id,
-            result: case_result,
-            time,
-            memory,
-            info: info.clone(),
-        });
-        time = 0;
-    }
-    // ^ 所有数据点测评完毕
-    state = State::Finished;
-    let mut it = test_cases.iter();
-    it.next();
-    if !it.any(|x| x.result != JudgeResult::Accepted) {
-        job_result = JudgeResult::Accepted;
+/// 在 JOB_LIST 中修改 id 对应的测评任务，并刷新其更新时间。
+///
+/// 只有任务状态发生变化时才写入数据文件：评测过程中的进度更新不保存，
+/// 重启时尚未评测完成的任务会重新评测，不需要这些中间结果。
+fn update_job<F: FnOnce(&mut JobResponse)>(id: i32, f: F) {
+    let mut lock = JOB_LIST.lock().unwrap();
+    if let Some(job) = lock.iter_mut().find(|x| x.id == id) {
+        let state = job.state.clone();
+        f(job);
+        job.updated_time = now();
+        if job.state != state {
+            store::save_job(job);
+        }
     }
+}
 
-    // 清理文件夹
-    fs::remove_dir_all(&temp_dir).unwrap();
+/// 当前时间，格式形如 2022-08-27T02:05:29.000Z
+fn now() -> String {
+    Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
 
-    // 返回正确响应
-    let res = JobResponse {
-        id,
-        created_time,
-        updated_time,
-        submission,
-        state,
-        result: job_result,
-        score,
-        cases: test_cases,
-    };
-    let mut lock = JOB_LIST.lock().unwrap();
-    lock.push(res.clone());
-    HttpResponse::Ok().json(res)
+/// 解析 ISO 8601 格式的时间，如 2022-08-27T02:05:29.000Z
+fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
 }
 
 #[get("/jobs")]
-async fn get_jobs(req: HttpRequest) -> impl Responder {
+async fn get_jobs(req: HttpRequest) -> Result<HttpResponse, ApiError> {
     let info = match web::Query::<GetJob>::from_query(req.query_string()) {
         Err(_) => {
-            return HttpResponse::BadRequest().json(Job {
-                code: 1,
-                reason: "ERR_INVALID_ARGUMENT".to_string(),
-                message: "Invalid argument".to_string(),
-            });
+            return Err(ApiError::invalid_argument("Invalid argument"));
         }
         Ok(info) => info,
     };
 
+    // 时间范围，格式为 ISO 8601，如 2022-08-27T02:05:29.000Z
+    let mut range = [None, None];
+    for (bound, time) in range.iter_mut().zip([&info.from, &info.to]) {
+        if let Some(time) = time {
+            match parse_time(time) {
+                Some(time) => *bound = Some(time),
+                None => {
+                    return Err(ApiError::invalid_argument(format!(
+                        "Invalid time '{}'.",
+                        time
+                    )));
+                }
+            }
+        }
+    }
+    let [from, to] = range;
+
+    // 用户名对应的用户 id，用户不存在时没有符合条件的测评
+    let user_name_id = info.user_name.as_ref().map(|name| {
+        let lock = USER_LIST.lock().unwrap();
+        lock.iter().find(|x| &x.name == name).and_then(|x| x.id)
+    });
+
     let lock = JOB_LIST.lock().unwrap();
-    let mut slice = lock.clone();
+    let mut slice: Vec<&JobResponse> = lock.iter().collect();
     // filter
+    if let Some(user_id) = info.user_id {
+        slice.retain(|x| x.submission.user_id == user_id);
+    }
+    if let Some(user_id) = user_name_id {
+        slice.retain(|x| Some(x.submission.user_id) == user_id);
+    }
+    if let Some(contest_id) = info.contest_id {
+        slice.retain(|x| x.submission.contest_id == contest_id);
+    }
+    if let Some(from) = from {
+        slice.retain(|x| matches!(parse_time(&x.created_time), Some(time) if time >= from));
+    }
+    if let Some(to) = to {
+        slice.retain(|x| matches!(parse_time(&x.created_time), Some(time) if time <= to));
+    }
     if info.language.is_some() {
-        slice = slice
-            .into_iter()
-            .filter(|x| &x.submission.language == info.language.as_ref().unwrap())
-            .collect();
+        slice.retain(|x| &x.submission.language == info.language.as_ref().unwrap());
     }
     if info.result.is_some() {
-        slice = slice
-            .into_iter()
-            .filter(|x| &x.result == info.result.as_ref().unwrap())
-            .collect();
+        slice.retain(|x| &x.result == info.result.as_ref().unwrap());
     }
     if info.problem_id.is_some() {
-        slice = slice
-            .into_iter()
-            .filter(|x| &x.submission.problem_id == info.problem_id.as_ref().unwrap())
-            .collect();
+        slice.retain(|x| &x.submission.problem_id == info.problem_id.as_ref().unwrap());
     }
     if info.state.is_some() {
-        slice = slice
-            .into_iter()
-            .filter(|x| &x.state == info.state.as_ref().unwrap())
-            .collect();
+        slice.retain(|x| &x.state == info.state.as_ref().unwrap());
     }
 
-    HttpResponse::Ok().json(slice)
+    // 排序
+    let sort = info.sort.unwrap_or(SortKey::CreatedTime);
+    slice.sort_by(|a, b| {
+        let ordering = match sort {
+            SortKey::Id => a.id.cmp(&b.id),
+            SortKey::CreatedTime => a.created_time.cmp(&b.created_time),
+            SortKey::Score => a
+                .score
+                .partial_cmp(&b.score)
+                .unwrap_or(std::cmp::Ordering::Equal),
+        };
+        if info.order == Some(Order::Desc) {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    // 分页，只序列化当前页的测评
+    let total = slice.len();
+    let page: Vec<serde_json::Value> = slice
+        .into_iter()
+        .skip(info.offset.unwrap_or(0))
+        .take(info.limit.unwrap_or(usize::MAX))
+        .map(|job| {
+            let mut value = serde_json::to_value(job).unwrap();
+            if info.source_code == Some(false) {
+                value["submission"]
+                    .as_object_mut()
+                    .unwrap()
+                    .remove("source_code");
+            }
+            value
+        })
+        .collect();
+    drop(lock);
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Total-Count", total))
+        .json(page))
 }
 
 #[get("/jobs/{jobid}")]
-async fn get_jobs_by_id(jobid: web::Path<String>) -> impl Responder {
+async fn get_jobs_by_id(jobid: web::Path<String>) -> Result<HttpResponse, ApiError> {
     eprintln!("jobid = {:?}", jobid);
     let id = match jobid.to_string().parse::<i32>() {
-        Err(_) => {
-            return HttpResponse::BadRequest().json(Job {
-                reason: "ERR_NOT_FOUND".to_string(),
-                code: 3,
-                message: format!("job {} not found.", jobid),
-            })
-        }
+        Err(_) => return Err(ApiError::not_found(format!("Job {} not found.", jobid))),
         Ok(id) => id,
     };
     eprintln!("id = {:?}", id);
     let lock = JOB_LIST.lock().unwrap();
-    let mut slice = lock.clone();
-    slice = slice.into_iter().filter(|x| x.id == id).collect();
-    HttpResponse::Ok().json(&slice[0])
+    match lock.iter().find(|x| x.id == id) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(ApiError::not_found(format!("Job {} not found.", id))),
+    }
 }
 
 #[put("jobs/{jobid}")]
-async fn put_jobs_by_id(jobid: web::Path<String>, config: web::Data<Config>) -> impl Responder {
+async fn put_jobs_by_id(
+    jobid: web::Path<String>,
+    config: web::Data<Config>,
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
     eprintln!("jobid = {:?}", jobid);
     let id = match jobid.to_string().parse::<i32>() {
-        Err(_) => {
-            return HttpResponse::BadRequest().json(Job {
-                reason: "ERR_NOT_FOUND".to_string(),
-                code: 3,
-                message: format!("job {} not found.", jobid),
-            })
-        }
+        Err(_) => return Err(ApiError::not_found(format!("Job {} not found.", jobid))),
         Ok(id) => id,
     };
     eprintln!("id = {:?}", id);
     let mut lock = JOB_LIST.lock().unwrap();
     let index = match lock.iter().position(|x| x.id == id) {
         Some(index) => index,
-        None => {
-            return HttpResponse::NotFound().json(Job {
-                reason: "ERR_NOT_FOUND".to_string(),
-                code: 3,
-                message: format!("job {} not found.", jobid),
-            })
-        }
+        None => return Err(ApiError::not_found(format!("Job {} not found.", jobid))),
     };
-    lock[index].updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+
+    // 只有评测完成的任务才能重新评测，避免同一任务被两个评测线程同时评测
+    if lock[index].state != State::Finished {
+        return Err(ApiError::invalid_state(format!("Job {} not finished.", id)));
+    }
 
     let current_language = &lock[index].submission.language;
-    let config_languages = &config.languages;
     let current_problem_id = &lock[index].submission.problem_id;
-    let config_problems = &config.problems;
-    if !config_languages.iter().any(|x| &x.name == current_language)
-        || !config_problems.iter().any(|x| &x.id == current_problem_id)
-    {
-        return HttpResponse::NotFound().json(Job {
-            code: 3,
-            reason: "ERR_NOT_FOUND".to_string(),
-            message: "HTTP 404 Not Found".to_string(),
-        });
+    let problem = config
+        .problems
+        .iter()
+        .find(|&x| &x.id == current_problem_id);
+    if !config.languages.iter().any(|x| &x.name == current_language) || problem.is_none() {
+        return Err(ApiError::not_found("HTTP 404 Not Found"));
     }
 
-    // 维护测评状态的变量
-    let mut state = State::Queueing; // 整体测评状态
-    let mut job_result = JudgeResult::Waiting; // 整体测评结果
-    let mut compilation_result = JudgeResult::Waiting;
-    let mut case_result = JudgeResult::Waiting; // 当前测试点测评结果
-
-    let mut language_iter = config_languages.iter();
-    let language = language_iter
-        .find(|&x| &x.name == current_language)
-        .unwrap();
-
-    // 维护当前测评对应的题目 problem
-    let mut problem_iter = config_problems.iter();
-    let problem = problem_iter.find(|&x| &x.id == current_problem_id).unwrap();
-
-    // 创建临时测评目录  e.g. TMPDIR/0/
-    let temp_dir = Path::new("TMPDIR").join(id.to_string());
-    fs::create_dir_all(&temp_dir).unwrap();
-
-    // # 创建源代码文件  e.g. main.rs
-    let src_file = temp_dir.join(&language.file_name);
-
-    let display = src_file.display();
-    let mut file = match File::create(&src_file) {
-        Err(why) => panic!("couldn't create {}: {:?}", display, why),
-        Ok(file) => file,
-    };
+    // 重置测评状态，重新加入评测队列
+    let job = &mut lock[index];
+    job.updated_time = now();
+    job.state = State::Queueing;
+    job.result = JudgeResult::Waiting;
+    job.score = 0.0;
+    job.cases = Judgement::waiting(problem.unwrap()).cases;
+    store::save_job(job);
+    let res = job.clone();
+    drop(lock);
 
-    match file.write_all(&lock[index].submission.source_code.as_bytes()) {
-        Err(why) => {
-            panic!("couldn't write to {}: {:?}", display, why)
-        }
-        Ok(_) => println!("successfully wrote to {}", display),
+    if queue.push(id).is_err() {
+        fail_job(id, "judge workers have exited".to_string());
+        return Err(ApiError::internal("Judge workers have exited."));
     }
-    // ^ 源码写入完毕
-
-    // # 根据编程语言配置，将源代码编译成可执行文件 e.g. main
+    Ok(HttpResponse::Ok().json(res))
+}
 
-    // 更新测评状态
-    state = State::Running;
-    job_result = JudgeResult::Running;
-    compilation_result = JudgeResult::Running;
+#[delete("jobs/{jobid}")]
+async fn delete_jobs_by_id(jobid: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let id = match jobid.to_string().parse::<i32>() {
+        Err(_) => return Err(ApiError::not_found(format!("Job {} not found.", jobid))),
+        Ok(id) => id,
+    };
+    let mut lock = JOB_LIST.lock().unwrap();
+    let job = match lock.iter_mut().find(|x| x.id == id) {
+        Some(job) => job,
+        None => return Err(ApiError::not_found(format!("Job {} not found.", id))),
+    };
 
-    // get language-specific commands
-    let commands = &language.command;
-    let commands: Vec<String> = commands
-        .iter()
-        .map(|x| {
-            if x == "%OUTPUT%" {
-                temp_dir.join("main").to_str().unwrap().to_string()
-            } else if x == "%INPUT%" {
-                src_file.to_str().unwrap().to_string()
-            } else {
-                x.to_string()
+    match job.state {
+        // 排队中的任务：评测线程取出后会直接跳过
+        State::Queueing => {
+            job.result = JudgeResult::Skipped;
+            for case in job.cases.iter_mut() {
+                case.result = JudgeResult::Skipped;
             }
-        })
-        .collect();
-    eprintln!("commands = {:?}", commands);
-
-    // 子进程：编译
-    let status = Command::new(&commands[0])
-        .args(&commands[1..])
-        .status()
-        .unwrap();
-    // ^ 编译完成
-
-    // 错误处理：编译
-    // 编译成功
-    if status.code().unwrap() == 0 {
-        compilation_result = JudgeResult::CompilationSuccess;
-    } else {
-        // 编译失败
-        compilation_result = JudgeResult::CompilationError;
-        job_result = JudgeResult::CompilationError;
-        state = State::Finished;
-    }
-
-    // #按照顺序对数据点进行评测
-
-    // 维护测试点信息
-    let problem_cases = &problem.cases;
-
-    // 维护测试结果相关信息
-    let mut score = 0.0;
-    let mut test_case_id = 0;
-    let mut test_cases: Vec<Case> = Vec::new();
-    let mut time = 0;
-    let memory = 0;
-    let info = "".to_string();
-
-    // case 0: 编译
-    test_cases.push(Case {
-        id: 0,
-        result: compilation_result,
-        time: 0,
-        memory,
-        info: info.clone(),
-    });
-
-    // other cases
-    for problem_case in problem_cases.iter() {
-        test_case_id += 1;
-
-        if job_result == JudgeResult::Running {
-            // 指定in out文件
-            let in_file = File::open(&problem_case.input_file).unwrap();
-            let out_file = File::create(temp_dir.join("test.out")).unwrap();
-
-            // 维护计时器s
-            let now = Instant::now();
-
-            let mut child = Command::new(temp_dir.join("main"))
-                .stdin(Stdio::from(in_file))
-                .stdout(Stdio::from(out_file))
-                .stderr(Stdio::null())
-                .spawn()
-                .unwrap();
-            let time_limit = problem_case.time_limit;
-            let status_code = match child
-                .wait_timeout(Duration::from_micros(time_limit))
-                .unwrap()
-            {
-                Some(status) => status.code(),
-                None => {
-                    // child hasn't exited yet
-                    child.kill().unwrap();
-                    child.wait().unwrap().code()
-                }
-            };
-
-            time = now.elapsed().as_micros() as u64;
-
-            // 错误处理: 程序运行返回值
-            if status_code == Some(101) {
-                case_result = JudgeResult::RuntimeError;
-                job_result = case_result;
-            } else if status_code == Some(0) {
-                // # 运行成功，比较test.out & file.ans
-
-                let out_str = fs::read_to_string(temp_dir.join("test.out")).unwrap();
-                let ans_str = fs::read_to_string(&problem_case.answer_file).unwrap();
-                eprintln!("out_str = {:?}", out_str);
-                eprintln!("ans_str = {:?}", ans_str);
-
-                if problem.typ == "standard" {
-                    let out_str_vec: Vec<String> = out_str
-                        .trim()
-                        .split("\n")
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    let ans_str_vec: Vec<String> = ans_str
-                        .trim()
-                        .split("\n")
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    eprintln!("out_str_vec = {:?}", out_str_vec);
-                    eprintln!("ans_str_vec = {:?}", ans_str_vec);
-                    if out_str_vec == ans_str_vec {
-                        score += &problem_case.score;
-                        case_result = JudgeResult::Accepted;
-                    } else {
-                        case_result = JudgeResult::WrongAnswer;
-                        job_result = JudgeResult::WrongAnswer;
-                    }
-                } else if problem.typ == "strict" {
-                    if out_str == ans_str {
-                        score += &problem_case.score;
-                        case_result = JudgeResult::Accepted;
-                    } else {
-                        case_result = JudgeResult::WrongAnswer;
-                        job_result = JudgeResult::WrongAnswer;
-                    }
-                }
-            } else if status_code == None {
-                // tle
-                case_result = JudgeResult::TimeLimitExceeded;
-                job_result = JudgeResult::TimeLimitExceeded;
-            } else {
+        }
+        // 正在评测的任务：通知评测线程杀死正在运行的程序，评测线程随后写入最终结果
+        State::Running => {
+            if let Some(cancel) = CANCEL_FLAGS.lock().unwrap().get(&id) {
+                cancel.store(true, Ordering::Relaxed);
             }
-        } else {
-            case_result = JudgeResult::Waiting;
         }
-
-        // 更新test_cases
-        test_cases.push(Case {
-            id: test_case_id,
-            result: case_result,
-            time,
-            memory,
-            info: info.clone(),
-        })
-    }
-    // ^ 所有数据点测评完毕
-    state = State::Finished;
-    let mut it = test_cases.iter();
-    it.next();
-    if !it.any(|x| x.result != JudgeResult::Accepted) {
-        job_result = JudgeResult::Accepted;
+        State::Finished | State::Canceled => {
+            return Err(ApiError::invalid_state(format!(
+                "Job {} not queuing or running.",
+                id
+            )));
+        }
     }
+    job.state = State::Canceled;
+    job.updated_time = now();
+    store::save_job(job);
+    Ok(HttpResponse::Ok().finish())
+}
 
-    // 清理文件夹
-    fs::remove_dir_all(&temp_dir).unwrap();
+/// 清空编译缓存，之后的评测（包括重新评测）都会重新编译
+#[delete("/internal/compile_cache")]
+async fn delete_compile_cache(config: web::Data<Config>) -> Result<HttpResponse, ApiError> {
+    match judge::purge_compile_cache(&config.judge) {
+        Ok(removed) => Ok(HttpResponse::Ok().json(PurgeResponse { removed })),
+        Err(err) => Err(ApiError::internal(format!(
+            "Cannot purge compile cache: {}",
+            err
+        ))),
+    }
+}
 
-    lock[index].cases = test_cases;
-    lock[index].score = score;
-    lock[index].result = job_result;
-    lock[index].state = state;
-    //
-    HttpResponse::Ok().json(&lock[index])
+#[derive(Serialize)]
+struct PurgeResponse {
+    removed: usize,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct JobResponse {
     pub id: i32,
     pub created_time: String,
@@ -709,12 +558,21 @@ pub struct JobResponse {
     pub cases: Vec<Case>,
 }
 
+impl JobResponse {
+    /// 用评测引擎给出的（阶段性）结果更新测评任务
+    fn apply(&mut self, judgement: &Judgement) {
+        self.result = judgement.result;
+        self.score = judgement.score;
+        self.cases = judgement.cases.clone();
+    }
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
 pub enum State {
     Queueing,
     Running,
     Finished,
-    // Canceled,
+    Canceled,
 }
 #[derive(Serialize, PartialEq, Clone, Copy, Deserialize, Debug)]
 pub enum JudgeResult {
@@ -727,24 +585,37 @@ pub enum JudgeResult {
     CompilationSuccess,
     #[serde(rename = "Wrong Answer")]
     WrongAnswer,
+    #[serde(rename = "Partially Correct")]
+    PartiallyCorrect,
     #[serde(rename = "Runtime Error")]
     RuntimeError,
     #[serde(rename = "Time Limit Exceeded")]
     TimeLimitExceeded,
-    // #[serde(rename = "Memory Limit Exceeded")]
-    // MemoryLimitExceeded,
-    // #[serde(rename = "System Error")]
-    // SystemError,
-    // #[serde(rename = "SPJ Error")]
-    // SPJError,
-    // Skipped,
+    #[serde(rename = "Idleness Limit Exceeded")]
+    IdlenessLimitExceeded,
+    #[serde(rename = "Memory Limit Exceeded")]
+    MemoryLimitExceeded,
+    #[serde(rename = "Output Limit Exceeded")]
+    OutputLimitExceeded,
+    #[serde(rename = "System Error")]
+    SystemError,
+    #[serde(rename = "SPJ Error")]
+    SPJError,
+    Skipped,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Case {
-    id: i32,
-    result: JudgeResult,
-    time: u64,
-    memory: u64,
-    info: String,
+    pub id: i32,
+    pub result: JudgeResult,
+    /// 运行时间（微秒），即 CPU 时间（用户态与内核态时间之和）
+    pub time: u64,
+    /// 墙上时间（微秒）
+    #[serde(default)]
+    pub wall_time: u64,
+    pub memory: u64,
+    /// 得分比例，0 到 1 之间
+    #[serde(default)]
+    pub score_ratio: f64,
+    pub info: String,
 }
@@ -1,4 +1,5 @@
 pub mod contests;
+pub mod error;
 pub mod hello;
 pub mod jobs;
 pub mod users;
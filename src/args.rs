@@ -7,6 +7,11 @@ pub struct Args {
     #[clap(short, long, value_parser)]
     pub config: String,
 
+    /// 启动时清空持久化数据
     #[clap(short, long, action)]
     pub flush_data: bool,
+
+    /// 持久化数据文件
+    #[clap(long, value_parser, default_value = "oj-data.jsonl")]
+    pub data_file: String,
 }
@@ -5,15 +5,23 @@ pub struct Config {
     pub server: Bind,
     pub problems: Vec<Problem>,
     pub languages: Vec<Language>,
+    #[serde(default)]
+    pub judge: Judge,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Bind {
     pub bind_address: String,
     pub bind_port: u16,
+    /// 额外监听的地址，每项形如 `0.0.0.0:8080`
+    #[serde(default)]
+    pub additional_addresses: Vec<String>,
+    /// 额外监听的 Unix domain socket 路径
+    pub unix_socket: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[allow(dead_code)]
 pub struct Problem {
     pub id: u32,
     pub name: String,
@@ -25,7 +33,47 @@ pub struct Problem {
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct Misc {
-    packing: Option<Vec<Vec<usize>>>,
+    /// 打包评测的分组，每组为测试点编号（从 1 开始）的列表
+    pub packing: Option<Vec<Vec<usize>>>,
+    /// special judge 命令，仅用于 `spj` 类型的题目
+    pub special_judge: Option<Vec<String>>,
+    /// 交互程序命令，仅用于 `interactive` 类型的题目
+    pub interactor: Option<Vec<String>>,
+    /// 竞争得分占比，仅用于 `dynamic_ranking` 类型的题目
+    pub dynamic_ranking_ratio: Option<f64>,
+    /// 程序输出大小限制（字节），缺省为 64MB
+    pub output_limit: Option<u64>,
+    /// 比较输出的方式，缺省时 `strict` 类型的题目逐字节比较，其他题目逐行比较。
+    /// 不用于 `spj` 和 `interactive` 类型的题目
+    pub comparator: Option<Comparator>,
+    /// `float` 比较方式允许的绝对误差或相对误差，缺省为 1e-6
+    pub epsilon: Option<f64>,
+    /// 为真时内置的比较方式（`strict` 除外）按相同的行或单词的比例给出部分分，缺省为假
+    pub partial_score: Option<bool>,
+    /// 评测策略，缺省为 `run_all`；比赛设置了评测策略时以比赛的为准
+    pub judging_policy: Option<JudgingPolicy>,
+}
+
+/// 评测策略
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JudgingPolicy {
+    /// 评测所有测试点
+    RunAll,
+    /// 第一个未通过的测试点之后的测试点都跳过，如 ICPC 赛制
+    StopOnFailure,
+}
+
+/// 内置的输出比较方式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    Strict,
+    Lines,
+    Tokens,
+    Float,
+    CaseInsensitive,
+    UnorderedLines,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -33,13 +81,203 @@ pub struct Case {
     pub score: f64,
     pub input_file: String,
     pub answer_file: String,
+    /// CPU 时间限制（微秒），0 表示不限制
     pub time_limit: u64,
+    /// 墙上时间限制（微秒），缺省时为 CPU 时间限制的两倍再加一秒
+    pub wall_time_limit: Option<u64>,
     pub memory_limit: u64,
 }
 
+impl Case {
+    /// 墙上时间限制（微秒），0 表示不限制
+    pub fn wall_time_limit(&self) -> u64 {
+        match self.wall_time_limit {
+            Some(limit) => limit,
+            None if self.time_limit == 0 => 0,
+            None => self.time_limit * 2 + 1_000_000,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Language {
     pub name: String,
     pub file_name: String,
+    /// 编译命令，为空时不编译（如解释型语言）
+    #[serde(default)]
     pub command: Vec<String>,
+    /// 运行命令，缺省时运行编译得到的 `%OUTPUT%`。
+    /// 命令中的 `%OUTPUT%`、`%INPUT%`、`%DIR%` 分别替换为编译输出、源代码文件与评测目录，
+    /// 参数中的 `%MEMORY%` 替换为内存限制（KB），不限制内存时省略该参数
+    pub run: Option<Vec<String>>,
+    /// 编译时间限制（微秒），0 表示不限制
+    #[serde(default = "default_compile_time_limit")]
+    pub compile_time_limit: u64,
+    /// 该语言的时间限制倍率
+    #[serde(default = "default_multiplier")]
+    pub time_multiplier: f64,
+    /// 该语言的内存限制倍率
+    #[serde(default = "default_multiplier")]
+    pub memory_multiplier: f64,
+}
+
+/// 评测队列相关配置，缺省时使用默认值
+#[derive(Debug, Deserialize, Clone)]
+pub struct Judge {
+    /// 后台评测线程数
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// 运行提交的程序和编译器时使用的沙箱
+    #[serde(default)]
+    pub sandbox: Sandbox,
+    /// 记录在编译结果中的编译器输出长度上限（字节）
+    #[serde(default = "default_max_compile_output")]
+    pub max_compile_output: usize,
+    /// 同一个任务中同时运行的测试点数上限，缺省为 1，即依次运行
+    #[serde(default = "default_parallel_cases")]
+    pub parallel_cases: usize,
+    /// 运行测试点使用的 CPU 核心编号。设置后每个测试点独占其中一个核心运行，
+    /// 所有评测线程同时运行的测试点总数不超过核心数；缺省为空，即不绑定核心
+    #[serde(default)]
+    pub cpus: Vec<usize>,
+    /// 所有评测线程同时运行的测试点总数上限，缺省为 CPU 核数，0 表示不限制
+    #[serde(default = "default_max_running_cases")]
+    pub max_running_cases: usize,
+    /// 编译缓存
+    #[serde(default)]
+    pub compile_cache: CompileCache,
+}
+
+impl Default for Judge {
+    fn default() -> Self {
+        Judge {
+            workers: default_workers(),
+            sandbox: Sandbox::default(),
+            max_compile_output: default_max_compile_output(),
+            parallel_cases: default_parallel_cases(),
+            cpus: Vec::new(),
+            max_running_cases: default_max_running_cases(),
+            compile_cache: CompileCache::default(),
+        }
+    }
+}
+
+/// 沙箱配置。命名空间或 seccomp 在当前系统上不可用时默认拒绝评测；
+/// 关闭 `required` 后会自动跳过，其余限制照常生效
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sandbox {
+    /// 是否在沙箱中运行提交的程序和编译器
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 命名空间或 seccomp 不可用时拒绝评测（结果为 `System Error`），而不是在沙箱外运行，缺省为 `true`
+    #[serde(default = "default_true")]
+    pub required: bool,
+    /// 文件系统无法设为只读（命名空间不可用）时，程序可以写入的单个文件大小上限（字节）。
+    /// 标准输出的大小只受题目的输出大小限制
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// 程序可以同时打开的文件数上限
+    #[serde(default = "default_max_open_files")]
+    pub max_open_files: u64,
+    /// 程序的线程数上限，0 表示不限制
+    #[serde(default = "default_max_processes")]
+    pub max_processes: u64,
+    /// 需要对程序和编译器隐藏的文件或目录。
+    /// 启动时会自动加入配置文件、持久化数据文件、所有题目的数据目录与编译缓存目录
+    #[serde(default)]
+    pub hidden_paths: Vec<String>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            enabled: true,
+            required: true,
+            max_file_size: default_max_file_size(),
+            max_open_files: default_max_open_files(),
+            max_processes: default_max_processes(),
+            hidden_paths: Vec::new(),
+        }
+    }
+}
+
+/// 编译缓存配置：重新评测或重复提交相同的代码时复用编译产物
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompileCache {
+    /// 是否缓存编译产物
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 缓存目录，默认位于评测临时目录中，对提交的程序不可见
+    #[serde(default = "default_compile_cache_dir")]
+    pub dir: String,
+    /// 缓存总大小上限（字节），超出时删除最久未使用的缓存
+    #[serde(default = "default_compile_cache_size")]
+    pub max_size: u64,
+}
+
+impl Default for CompileCache {
+    fn default() -> Self {
+        CompileCache {
+            enabled: true,
+            dir: default_compile_cache_dir(),
+            max_size: default_compile_cache_size(),
+        }
+    }
+}
+
+fn default_compile_cache_dir() -> String {
+    "TMPDIR/cache".to_string()
+}
+
+fn default_compile_cache_size() -> u64 {
+    256 << 20
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 默认单个文件大小上限：64MB
+fn default_max_file_size() -> u64 {
+    64 << 20
+}
+
+fn default_max_open_files() -> u64 {
+    64
+}
+
+fn default_max_processes() -> u64 {
+    64
+}
+
+/// 默认编译时间限制：30 秒
+fn default_compile_time_limit() -> u64 {
+    30_000_000
+}
+
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+/// 默认编译器输出长度上限：8KB
+fn default_max_compile_output() -> usize {
+    8 << 10
+}
+
+fn default_parallel_cases() -> usize {
+    1
+}
+
+/// 默认评测线程数：CPU 核数
+fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// 默认同时运行的测试点数：CPU 核数
+fn default_max_running_cases() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
 }
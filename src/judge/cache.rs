@@ -0,0 +1,190 @@
+//! 编译缓存：以语言、编译命令和源代码的哈希为键保存编译产物，重新评测或重复提交相同的代码时不必再次编译。
+//!
+//! 每个缓存项是缓存目录下以键命名的目录，其中 `files` 为编译后评测目录中除源代码外的文件，
+//! `output` 为编译器的输出，`used` 为最近一次使用的时间。缓存总大小超出上限时删除最久未使用的缓存项。
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+use crate::config::{CompileCache, Language};
+
+lazy_static! {
+    /// 同一时刻只有一个评测线程读写缓存目录
+    static ref CACHE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// 缓存的键：语言名称、源代码文件名、编译命令与源代码的 SHA-256
+pub fn key(language: &Language, source_code: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [&language.name, &language.file_name]
+        .into_iter()
+        .chain(language.command.iter())
+    {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(source_code.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+/// 查找缓存，命中时把编译产物复制到 `work_dir` 并返回编译器的输出
+pub fn restore(config: &CompileCache, key: &str, work_dir: &Path) -> Option<String> {
+    if !config.enabled {
+        return None;
+    }
+    let _lock = CACHE_LOCK.lock().unwrap();
+    let entry = Path::new(&config.dir).join(key);
+    let output = fs::read_to_string(entry.join("output")).ok()?;
+    let result = copy_dir(&entry.join("files"), work_dir).and_then(|_| touch(&entry));
+    if let Err(err) = result {
+        log::warn!("cannot restore compile cache {}: {}", entry.display(), err);
+        return None;
+    }
+    Some(output)
+}
+
+/// 把 `work_dir` 中除 `exclude`（如源代码）外的文件作为 `key` 的编译产物加入缓存，并按大小上限淘汰缓存。
+/// 缓存出错只记录日志，不影响评测
+pub fn store(config: &CompileCache, key: &str, work_dir: &Path, exclude: &[&str], output: &str) {
+    if !config.enabled {
+        return;
+    }
+    let _lock = CACHE_LOCK.lock().unwrap();
+    let dir = Path::new(&config.dir);
+    let entry = dir.join(key);
+    // 先写入临时目录，完整后再改名，避免留下不完整的缓存项
+    let tmp = dir.join(format!("{}.tmp", key));
+    let result = (|| {
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(tmp.join("files"))?;
+        for file in fs::read_dir(work_dir)? {
+            let file = file?;
+            let name = file.file_name();
+            if exclude.iter().any(|x| name == *x) {
+                continue;
+            }
+            copy(&file.path(), &tmp.join("files").join(&name))?;
+        }
+        fs::write(tmp.join("output"), output)?;
+        touch(&tmp)?;
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
+        }
+        fs::rename(&tmp, &entry)
+    })();
+    if let Err(err) = result {
+        log::warn!("cannot store compile cache {}: {}", entry.display(), err);
+        let _ = fs::remove_dir_all(&tmp);
+        return;
+    }
+    if let Err(err) = evict(config) {
+        log::warn!("cannot evict compile cache: {}", err);
+    }
+}
+
+/// 删除所有缓存项，返回删除的个数
+pub fn purge(config: &CompileCache) -> io::Result<usize> {
+    let _lock = CACHE_LOCK.lock().unwrap();
+    let mut count = 0;
+    for entry in read_entries(Path::new(&config.dir))? {
+        fs::remove_dir_all(entry.path())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 缓存总大小超出上限时，从最久未使用的开始删除缓存项
+fn evict(config: &CompileCache) -> io::Result<()> {
+    let mut entries = Vec::new();
+    let mut total = 0;
+    for entry in read_entries(Path::new(&config.dir))? {
+        let path = entry.path();
+        let size = dir_size(&path)?;
+        let used: u128 = fs::read_to_string(path.join("used"))
+            .ok()
+            .and_then(|x| x.trim().parse().ok())
+            .unwrap_or(0);
+        total += size;
+        entries.push((used, size, path));
+    }
+    entries.sort_by_key(|x| x.0);
+    for (_, size, path) in entries {
+        if total <= config.max_size {
+            break;
+        }
+        log::info!("evicting compile cache {}", path.display());
+        fs::remove_dir_all(&path)?;
+        total -= size;
+    }
+    Ok(())
+}
+
+/// 缓存目录中的所有缓存项，缓存目录不存在时为空
+fn read_entries(dir: &Path) -> io::Result<Vec<fs::DirEntry>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut result = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            result.push(entry);
+        }
+    }
+    Ok(result)
+}
+
+/// 记录缓存项最近一次使用的时间（毫秒）
+fn touch(entry: &Path) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    fs::write(entry.join("used"), now.to_string())
+}
+
+/// 复制文件或目录，保留文件权限
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::metadata(from)?.is_dir() {
+        copy_dir(from, to)
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
@@ -0,0 +1,447 @@
+//! 比较程序输出与答案。
+//!
+//! 除 `strict` 逐字节比较外，`lines` 忽略行首尾空白和首尾空行后逐行比较，`tokens` 按空白分隔后逐个单词比较，
+//! `float` 在此基础上允许数值有 `misc.epsilon`（默认 1e-6）的误差，`case_insensitive` 同 `lines` 但不区分大小写，
+//! `unordered_lines` 不考虑行的顺序。开启部分分时按相同的行或单词数占较多一方的比例给分。
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+use crate::config::Comparator;
+
+/// 浮点数比较缺省的允许误差
+const DEFAULT_EPSILON: f64 = 1e-6;
+
+/// 按单词比较时读入内存的单词长度上限（字节）
+const MAX_TOKEN_LEN: usize = 4096;
+
+/// 按照比较方式比较程序输出与标准答案，返回得分比例（0 到 1 之间，1 表示完全一致）
+///
+/// - `strict`：逐字节比较
+/// - `lines`：忽略整体首尾空白以及每行首尾空白后逐行比较
+/// - `case_insensitive`：同 `lines`，但不区分大小写
+/// - `tokens`：按空白分隔为单词后逐个比较，忽略空白的种类和数量
+/// - `float`：同 `tokens`，但两个单词都是数时，绝对误差或相对误差不超过 `epsilon`（缺省为 1e-6）即视为相同
+/// - `unordered_lines`：忽略每行首尾空白和空行后，不考虑顺序比较所有行
+///
+/// `partial` 为假时输出必须完全一致，否则得分为 0；为真时除 `strict` 外，
+/// 得分比例为相同的行（或单词）数占输出与答案中较多一方行（或单词）数的比例。
+///
+/// 都以流的方式逐个字符读取和比较，不会把整个输出或其中的一行读入内存；
+/// `unordered_lines` 需要在内存中记录标准答案的所有行。
+pub fn compare<R: BufRead, S: BufRead>(
+    comparator: Comparator,
+    epsilon: Option<f64>,
+    partial: bool,
+    out: R,
+    ans: S,
+) -> io::Result<f64> {
+    let epsilon = epsilon.unwrap_or(DEFAULT_EPSILON);
+    match comparator {
+        Comparator::Strict => Ok(if compare_bytes(out, ans)? { 1.0 } else { 0.0 }),
+        Comparator::Lines => compare_lines(out, ans, partial, |a, b| a == b),
+        Comparator::CaseInsensitive => compare_lines(out, ans, partial, |a, b| {
+            a == b || a.to_lowercase().eq(b.to_lowercase())
+        }),
+        Comparator::Tokens => compare_tokens(out, ans, partial, |a, b| a == b),
+        Comparator::Float => compare_tokens(out, ans, partial, |a, b| float_eq(a, b, epsilon)),
+        Comparator::UnorderedLines => compare_unordered_lines(out, ans, partial),
+    }
+}
+
+/// 逐字节比较
+fn compare_bytes<R: BufRead, S: BufRead>(mut out: R, mut ans: S) -> io::Result<bool> {
+    loop {
+        let out_buf = out.fill_buf()?;
+        let ans_buf = ans.fill_buf()?;
+        if out_buf.is_empty() || ans_buf.is_empty() {
+            return Ok(out_buf.is_empty() && ans_buf.is_empty());
+        }
+        let len = out_buf.len().min(ans_buf.len());
+        if out_buf[..len] != ans_buf[..len] {
+            return Ok(false);
+        }
+        out.consume(len);
+        ans.consume(len);
+    }
+}
+
+/// 忽略每行首尾空白以及开头和末尾的空行后，用 `eq` 逐个字符比较每一行
+fn compare_lines<R: BufRead, S: BufRead, F: Fn(char, char) -> bool>(
+    out: R,
+    ans: S,
+    partial: bool,
+    eq: F,
+) -> io::Result<f64> {
+    let mut out = Text::new(out);
+    let mut ans = Text::new(ans);
+    let mut count = Count::default();
+    loop {
+        let same = match (out.next_line()?, ans.next_line()?) {
+            (None, None) => return Ok(count.ratio()),
+            (Some(a), Some(b)) => compare_line(&mut out, &mut ans, &eq)? && a == b,
+            (Some(_), None) => {
+                out.skip_line()?;
+                false
+            }
+            (None, Some(_)) => {
+                ans.skip_line()?;
+                false
+            }
+        };
+        if !same && !partial {
+            return Ok(0.0);
+        }
+        count.add(same);
+    }
+}
+
+/// 比较两边当前行（已跳过行首空白）的其余部分，忽略行尾空白，比较后两边都读到下一行开头
+fn compare_line<R: BufRead, S: BufRead, F: Fn(char, char) -> bool>(
+    out: &mut Text<R>,
+    ans: &mut Text<S>,
+    eq: &F,
+) -> io::Result<bool> {
+    let same = loop {
+        match (out.peek_in_line()?, ans.peek_in_line()?) {
+            (None, None) => break true,
+            (Some(a), Some(b)) if !a.is_whitespace() && !b.is_whitespace() => {
+                if !eq(a, b) {
+                    break false;
+                }
+                out.bump();
+                ans.bump();
+            }
+            _ => {
+                // 至少一边是空白：两边的空白完全相同且之后都还有内容，或者两边都只剩行尾空白时相同
+                while let (Some(a), Some(b)) = (out.peek_in_line()?, ans.peek_in_line()?) {
+                    if a != b || !a.is_whitespace() {
+                        break;
+                    }
+                    out.bump();
+                    ans.bump();
+                }
+                match (out.peek_in_line()?, ans.peek_in_line()?) {
+                    (Some(a), Some(b)) if !a.is_whitespace() && !b.is_whitespace() => continue,
+                    _ => {}
+                }
+                out.skip_whitespace_in_line()?;
+                ans.skip_whitespace_in_line()?;
+                break out.peek_in_line()?.is_none() && ans.peek_in_line()?.is_none();
+            }
+        }
+    };
+    out.skip_line()?;
+    ans.skip_line()?;
+    Ok(same)
+}
+
+/// 按空白分隔为单词后用 `eq` 逐个比较。
+///
+/// 单词只读入不超过 [`MAX_TOKEN_LEN`] 字节，更长的单词逐个字符精确比较
+fn compare_tokens<R: BufRead, S: BufRead, F: Fn(&str, &str) -> bool>(
+    out: R,
+    ans: S,
+    partial: bool,
+    eq: F,
+) -> io::Result<f64> {
+    let mut out = Text::new(out);
+    let mut ans = Text::new(ans);
+    let mut out_token = String::new();
+    let mut ans_token = String::new();
+    let mut count = Count::default();
+    loop {
+        let same = match (out.next_token()?, ans.next_token()?) {
+            (false, false) => return Ok(count.ratio()),
+            (true, true) => {
+                let out_complete = out.read_token(&mut out_token, MAX_TOKEN_LEN)?;
+                let ans_complete = ans.read_token(&mut ans_token, MAX_TOKEN_LEN)?;
+                if out_complete && ans_complete {
+                    eq(&out_token, &ans_token)
+                } else {
+                    out_token == ans_token && compare_token_rest(&mut out, &mut ans)?
+                }
+            }
+            _ => false,
+        };
+        out.skip_token()?;
+        ans.skip_token()?;
+        if !same && !partial {
+            return Ok(0.0);
+        }
+        count.add(same);
+    }
+}
+
+/// 逐个字符精确比较两边当前单词的其余部分
+fn compare_token_rest<R: BufRead, S: BufRead>(
+    out: &mut Text<R>,
+    ans: &mut Text<S>,
+) -> io::Result<bool> {
+    loop {
+        let a = out.peek()?.filter(|x| !x.is_whitespace());
+        let b = ans.peek()?.filter(|x| !x.is_whitespace());
+        match (a, b) {
+            (None, None) => return Ok(true),
+            (Some(a), Some(b)) if a == b => {
+                out.bump();
+                ans.bump();
+            }
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// 不考虑顺序比较去掉首尾空白后非空的行：先统计标准答案中每行出现的次数，再逐行核对输出。
+///
+/// 输出中比标准答案的每一行都长的行不可能相同，不读入内存
+fn compare_unordered_lines<R: BufRead, S: BufRead>(
+    out: R,
+    ans: S,
+    partial: bool,
+) -> io::Result<f64> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut ans_lines = 0;
+    let mut max_len = 0;
+    let mut ans = Text::new(ans);
+    while let Some(line) = ans.read_line(usize::MAX)? {
+        let line = line.unwrap_or_default();
+        max_len = max_len.max(line.len());
+        *counts.entry(line).or_insert(0) += 1;
+        ans_lines += 1;
+    }
+    let mut out_lines = 0;
+    let mut matched = 0;
+    let mut out = Text::new(out);
+    while let Some(line) = out.read_line(max_len)? {
+        out_lines += 1;
+        match line.and_then(|x| counts.get_mut(&x)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                matched += 1;
+            }
+            _ if !partial => return Ok(0.0),
+            _ => {}
+        }
+    }
+    let count = Count {
+        matched,
+        total: out_lines.max(ans_lines),
+    };
+    if count.matched < count.total && !partial {
+        return Ok(0.0);
+    }
+    Ok(count.ratio())
+}
+
+/// 逐个比较时相同的个数与总个数
+#[derive(Default)]
+struct Count {
+    matched: usize,
+    total: usize,
+}
+
+impl Count {
+    fn add(&mut self, same: bool) {
+        self.total += 1;
+        if same {
+            self.matched += 1;
+        }
+    }
+
+    /// 相同的比例，输出和答案都为空时为 1
+    fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.matched as f64 / self.total as f64
+        }
+    }
+}
+
+/// 两个单词相同，或都是数且绝对误差或相对误差不超过 `epsilon`
+fn float_eq(a: &str, b: &str, epsilon: f64) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => {
+            let error = (x - y).abs();
+            error <= epsilon || error <= epsilon * y.abs()
+        }
+        _ => false,
+    }
+}
+
+/// 按字符读取的文本，非法的 UTF-8 字节读作 U+FFFD。
+///
+/// 每次只向前看一个字符，行和单词都在读取的同时比较，不会整个读入内存
+struct Text<R> {
+    reader: R,
+    /// 已经解码但还没有被读走的字符，`Some(None)` 表示已经读到末尾
+    peeked: Option<Option<char>>,
+    /// 是否已经读到第一个非空行，开头的空行被忽略
+    started: bool,
+}
+
+impl<R: BufRead> Text<R> {
+    fn new(reader: R) -> Self {
+        Text {
+            reader,
+            peeked: None,
+            started: false,
+        }
+    }
+
+    /// 下一个字符，读到末尾时为 `None`
+    fn peek(&mut self) -> io::Result<Option<char>> {
+        if let Some(c) = self.peeked {
+            return Ok(c);
+        }
+        let c = self.decode()?;
+        self.peeked = Some(c);
+        Ok(c)
+    }
+
+    /// 读走 `peek` 得到的字符
+    fn bump(&mut self) {
+        self.peeked = None;
+    }
+
+    fn decode(&mut self) -> io::Result<Option<char>> {
+        let first = match self.next_byte()? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        let len = match first {
+            0x00..=0x7f => return Ok(Some(first as char)),
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for slot in &mut bytes[1..len] {
+            match self.reader.fill_buf()?.first() {
+                Some(&byte) if byte & 0xc0 == 0x80 => {
+                    *slot = byte;
+                    self.reader.consume(1);
+                }
+                _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+            }
+        }
+        Ok(Some(
+            std::str::from_utf8(&bytes[..len])
+                .ok()
+                .and_then(|x| x.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        ))
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.reader.fill_buf()?.first().copied();
+        if byte.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// 当前行的下一个字符，读到行尾或末尾时为 `None`
+    fn peek_in_line(&mut self) -> io::Result<Option<char>> {
+        Ok(self.peek()?.filter(|&x| x != '\n'))
+    }
+
+    fn skip_whitespace_in_line(&mut self) -> io::Result<()> {
+        while self.peek_in_line()?.is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+        Ok(())
+    }
+
+    /// 读到下一行开头
+    fn skip_line(&mut self) -> io::Result<()> {
+        while self.peek_in_line()?.is_some() {
+            self.bump();
+        }
+        self.bump();
+        Ok(())
+    }
+
+    /// 跳过空行和行首空白，读到下一个非空行的第一个字符，返回它之前（不含开头）的空行数；
+    /// 之后只剩空行时返回 `None`，末尾的空行被忽略
+    fn next_line(&mut self) -> io::Result<Option<usize>> {
+        let mut blank_lines = 0;
+        loop {
+            self.skip_whitespace_in_line()?;
+            match self.peek()? {
+                None => return Ok(None),
+                Some('\n') => {
+                    self.bump();
+                    blank_lines += 1;
+                }
+                Some(_) => break,
+            }
+        }
+        if !self.started {
+            self.started = true;
+            blank_lines = 0;
+        }
+        Ok(Some(blank_lines))
+    }
+
+    /// 读出下一个非空行去掉首尾空白后的内容；比 `limit` 字节长的行不读入内存，读作 `Some(None)`。
+    /// 之后只剩空行时返回 `None`
+    fn read_line(&mut self, limit: usize) -> io::Result<Option<Option<String>>> {
+        if self.next_line()?.is_none() {
+            return Ok(None);
+        }
+        let mut line = String::new();
+        let mut too_long = false;
+        while let Some(c) = self.peek_in_line()? {
+            self.bump();
+            // 超出长度的部分如果都是空白，就是被忽略的行尾空白
+            if line.len() + c.len_utf8() > limit {
+                too_long |= !c.is_whitespace();
+            } else if !too_long {
+                line.push(c);
+            }
+        }
+        self.bump();
+        if too_long {
+            return Ok(Some(None));
+        }
+        Ok(Some(Some(line.trim_end().to_string())))
+    }
+
+    /// 跳过空白，读到下一个单词的第一个字符，没有更多单词时返回 `false`
+    fn next_token(&mut self) -> io::Result<bool> {
+        while self.peek()?.is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+        Ok(self.peek()?.is_some())
+    }
+
+    /// 把当前单词的至多 `limit` 字节读入 `token`，返回是否读完了整个单词
+    fn read_token(&mut self, token: &mut String, limit: usize) -> io::Result<bool> {
+        token.clear();
+        while let Some(c) = self.peek()? {
+            if c.is_whitespace() {
+                return Ok(true);
+            }
+            if token.len() + c.len_utf8() > limit {
+                return Ok(false);
+            }
+            token.push(c);
+            self.bump();
+        }
+        Ok(true)
+    }
+
+    /// 读完当前单词的其余部分
+    fn skip_token(&mut self) -> io::Result<()> {
+        while self.peek()?.is_some_and(|x| !x.is_whitespace()) {
+            self.bump();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! 运行测试点的名额与 CPU 核心：所有评测线程同时运行的测试点总数不超过配置中的上限；
+//! 配置了核心时每个核心同一时刻只运行一个测试点，程序被绑定在分配到的核心上运行，
+//! 避免相互抢占导致运行时间不稳定。
+
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use crate::config;
+
+lazy_static! {
+    static ref RUNNING: Mutex<Running> = Mutex::new(Running {
+        cases: 0,
+        cpus: HashSet::new(),
+    });
+    static ref CPU_RELEASED: Condvar = Condvar::new();
+}
+
+/// 所有评测线程正在运行的测试点
+struct Running {
+    /// 测试点数
+    cases: usize,
+    /// 正在运行测试点的核心
+    cpus: HashSet<usize>,
+}
+
+/// 等待核心时检查任务是否被取消的间隔
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 分配到的运行名额（以及核心），释放时归还
+pub struct Cpu {
+    /// 没有配置核心时为 `None`
+    id: Option<usize>,
+}
+
+impl Cpu {
+    /// 等待运行名额：正在运行的测试点数达到 `config.max_running_cases` 时等待；
+    /// 配置了 `config.cpus` 时还要从中分配一个空闲的核心，没有空闲核心时等待。
+    ///
+    /// 等待时任务被取消时返回 `None`。
+    pub fn acquire(config: &config::Judge, cancel: &AtomicBool) -> Option<Self> {
+        let mut running = RUNNING.lock().unwrap();
+        loop {
+            if config.max_running_cases == 0 || running.cases < config.max_running_cases {
+                if config.cpus.is_empty() {
+                    running.cases += 1;
+                    return Some(Cpu { id: None });
+                }
+                if let Some(&id) = config.cpus.iter().find(|x| !running.cpus.contains(x)) {
+                    running.cases += 1;
+                    running.cpus.insert(id);
+                    return Some(Cpu { id: Some(id) });
+                }
+            }
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            running = CPU_RELEASED
+                .wait_timeout(running, CANCEL_CHECK_INTERVAL)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// 让 `command` 只在分配到的核心上运行，没有配置核心时不做任何事
+    pub fn pin(&self, command: &mut Command) {
+        let id = match self.id {
+            Some(id) => id,
+            None => return,
+        };
+        // 子进程在 fork 之后、exec 之前不能安全地分配内存，提前准备好 cpu_set
+        let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        unsafe { libc::CPU_SET(id, &mut cpu_set) };
+        unsafe {
+            command.pre_exec(move || {
+                let size = std::mem::size_of::<libc::cpu_set_t>();
+                if libc::sched_setaffinity(0, size, &cpu_set) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+impl Drop for Cpu {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap();
+        running.cases -= 1;
+        if let Some(id) = self.id {
+            running.cpus.remove(&id);
+        }
+        CPU_RELEASED.notify_all();
+    }
+}
@@ -0,0 +1,136 @@
+//! 运行交互题：程序的标准输入输出通过管道与交互程序相连，两者同时运行并都受时间限制。
+//!
+//! 交互程序返回 0 表示通过、返回 1 表示答案错误，其标准错误作为测试点的附加信息，
+//! 运行失败或返回其他值时结果为 `SPJ Error`。只有程序正常退出或因交互程序先结束而被
+//! `SIGPIPE` 终止时才以交互程序的结果为准，否则结果为 `Runtime Error`。
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicBool;
+use std::thread;
+
+use super::runner::{self, Limits, RunResult};
+use super::Verdict;
+use crate::api::jobs::JudgeResult;
+
+/// 交互程序的运行时间限制（微秒），程序没有墙上时间限制时使用
+const INTERACTOR_TIME_LIMIT: u64 = 10_000_000;
+
+/// 记录在测试点结果中的交互程序标准错误长度上限（字节）
+const MAX_MESSAGE: usize = 1024;
+
+/// 运行交互题：程序的标准输入和标准输出分别连接到交互程序的标准输出和标准输入，
+/// 两者同时运行，返回程序的运行结果以及交互程序给出的测试点结果与附加信息。
+///
+/// 交互命令中的 `%INPUT%`、`%ANSWER%` 分别替换为测试点输入文件与答案文件。
+/// 交互程序返回 0 表示 `Accepted`，返回 1 表示 `Wrong Answer`，其标准错误作为附加信息；
+/// 交互程序运行失败、超时或返回其他值时结果为 `SPJ Error`。
+/// 无法创建管道或无法启动程序时返回错误信息。
+pub fn interact(
+    mut program: Command,
+    limits: &Limits,
+    command: &[String],
+    input_file: &str,
+    answer_file: &str,
+    work_dir: &Path,
+    cancel: &AtomicBool,
+) -> Result<(RunResult, Verdict), String> {
+    if command.is_empty() {
+        return Err("interactor is not configured".to_string());
+    }
+    let commands: Vec<String> = command
+        .iter()
+        .map(|x| match x.as_str() {
+            "%INPUT%" => input_file.to_string(),
+            "%ANSWER%" => answer_file.to_string(),
+            _ => x.to_string(),
+        })
+        .collect();
+
+    let (program_in, interactor_out) =
+        pipe().map_err(|err| format!("cannot create pipe: {}", err))?;
+    let (interactor_in, program_out) =
+        pipe().map_err(|err| format!("cannot create pipe: {}", err))?;
+    let message_path = work_dir.join("interactor.err");
+    let message_file = File::create(&message_path)
+        .map_err(|err| format!("cannot create {}: {}", message_path.display(), err))?;
+
+    program
+        .stdin(Stdio::from(program_in))
+        .stdout(Stdio::from(program_out))
+        .stderr(Stdio::null());
+    let mut interactor = Command::new(&commands[0]);
+    interactor
+        .args(&commands[1..])
+        .stdin(Stdio::from(interactor_in))
+        .stdout(Stdio::from(interactor_out))
+        .stderr(Stdio::from(message_file));
+    let interactor_time_limit = if limits.wall_time_limit == 0 {
+        INTERACTOR_TIME_LIMIT
+    } else {
+        limits.wall_time_limit + 1_000_000
+    };
+    let interactor_limits = Limits {
+        time_limit: interactor_time_limit,
+        wall_time_limit: interactor_time_limit,
+        memory_limit: 0,
+        output_limit: 0,
+        max_threads: 0,
+    };
+
+    // 两个进程各在一个线程中运行。`Command` 持有管道的一端，进程结束后立即释放，
+    // 使另一个进程能读到文件结尾而不是一直等待
+    let (program_result, interactor_result) = thread::scope(|scope| {
+        let interactor = scope.spawn(move || {
+            let result = runner::run(&mut interactor, &interactor_limits, cancel);
+            drop(interactor);
+            result
+        });
+        let program_result = runner::run(&mut program, limits, cancel);
+        drop(program);
+        (program_result, interactor.join())
+    });
+    let program_result = program_result.map_err(|err| format!("cannot run program: {}", err))?;
+
+    let verdict = match interactor_result {
+        Ok(Ok(run_result)) => verdict(&run_result, &message_path),
+        Ok(Err(err)) => interactor_error(format!("cannot run interactor: {}", err)),
+        Err(_) => interactor_error("interactor thread panicked".to_string()),
+    };
+    Ok((program_result, verdict))
+}
+
+/// 根据交互程序的运行结果判定测试点结果
+fn verdict(run_result: &RunResult, message_path: &Path) -> Verdict {
+    if run_result.canceled {
+        return interactor_error("interactor was canceled".to_string());
+    }
+    if run_result.time_limit_exceeded || run_result.wall_time_limit_exceeded {
+        return interactor_error("interactor timed out".to_string());
+    }
+    let message = super::read_truncated(message_path, MAX_MESSAGE)
+        .trim()
+        .to_string();
+    match run_result.code {
+        Some(0) => Verdict::new(JudgeResult::Accepted, message),
+        Some(1) => Verdict::new(JudgeResult::WrongAnswer, message),
+        Some(code) => interactor_error(format!("interactor exited with code {}", code)),
+        None => interactor_error("interactor was killed by a signal".to_string()),
+    }
+}
+
+fn interactor_error(info: String) -> Verdict {
+    Verdict::new(JudgeResult::SPJError, info)
+}
+
+/// 创建一个管道，返回读端和写端。两端都设置了 `O_CLOEXEC`，不会被其他子进程继承
+fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))) }
+}
@@ -0,0 +1,1694 @@
+//! 评测引擎：根据题目和语言配置编译、运行提交的代码并比较输出。
+//!
+//! 评测引擎不依赖 HTTP 服务与全局状态，`POST /jobs`、重新评测等都通过 [`judge`] 完成评测。
+
+mod cache;
+mod compare;
+mod cpus;
+mod interactor;
+mod runner;
+mod sandbox;
+mod spj;
+
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::api::jobs::{Case, JudgeResult, PostJob};
+use crate::config::{self, Comparator, JudgingPolicy, Language, Problem};
+use cpus::Cpu;
+use runner::{Limits, RunResult};
+use sandbox::Policy;
+
+/// 题目没有指定输出大小限制时使用的限制（字节）：64MB
+const DEFAULT_OUTPUT_LIMIT: u64 = 64 << 20;
+
+/// 清空编译缓存，返回删除的缓存项数
+pub fn purge_compile_cache(config: &config::Judge) -> io::Result<usize> {
+    cache::purge(&config.compile_cache)
+}
+
+/// 一次评测的结果，`cases[0]` 为编译结果，其余依次为各个测试点的结果
+#[derive(Debug, Clone)]
+pub struct Judgement {
+    pub result: JudgeResult,
+    pub score: f64,
+    pub cases: Vec<Case>,
+}
+
+impl Judgement {
+    /// 尚未开始评测时的结果：编译及所有测试点均为等待状态
+    pub fn waiting(problem: &Problem) -> Self {
+        let cases = (0..=problem.cases.len())
+            .map(|i| Case {
+                id: i as i32,
+                result: JudgeResult::Waiting,
+                time: 0,
+                wall_time: 0,
+                memory: 0,
+                score_ratio: 0.0,
+                info: String::new(),
+            })
+            .collect();
+        Judgement {
+            result: JudgeResult::Waiting,
+            score: 0.0,
+            cases,
+        }
+    }
+}
+
+/// 检查程序输出得到的测试点结果
+#[derive(Debug, Clone)]
+struct Verdict {
+    result: JudgeResult,
+    /// 得分比例，0 到 1 之间
+    score_ratio: f64,
+    info: String,
+}
+
+impl Verdict {
+    /// 不给部分分的结果：通过时得到全部分数，否则不得分
+    fn new(result: JudgeResult, info: String) -> Self {
+        let score_ratio = if result == JudgeResult::Accepted {
+            1.0
+        } else {
+            0.0
+        };
+        Verdict {
+            result,
+            score_ratio,
+            info,
+        }
+    }
+
+    /// 按得分比例给出的结果：比例为 1 时通过，为 0 时答案错误，否则部分正确
+    fn partial(score_ratio: f64, info: String) -> Self {
+        let result = if score_ratio >= 1.0 {
+            JudgeResult::Accepted
+        } else if score_ratio <= 0.0 {
+            JudgeResult::WrongAnswer
+        } else {
+            JudgeResult::PartiallyCorrect
+        };
+        Verdict {
+            result,
+            score_ratio: score_ratio.clamp(0.0, 1.0),
+            info,
+        }
+    }
+}
+
+/// 在 `work_dir` 中评测一份提交，返回最终结果。
+///
+/// 每当编译或某个测试点状态发生变化时调用 `progress`，以便调用者实时更新评测进度。
+/// `cancel` 被置位后会杀死正在运行的程序并停止评测，尚未完成的编译和测试点标记为跳过。
+/// 编译器的输出（截断到 `config.max_compile_output` 字节）记录在编译结果的 `info` 中。
+/// 题目的评测策略为 `stop_on_failure` 时，第一个未通过的测试点之后的测试点都标记为跳过。
+/// 提交的程序按照 `config.sandbox` 在沙箱中运行。评测结束后 `work_dir` 会被删除。
+///
+/// 评测系统自身的错误（如无法创建临时目录、无法打开测试数据、无法启动编译器）不会 panic，
+/// 而是使对应的编译或测试点结果及整体结果为 `System Error`，并在 `info` 中给出原因。
+pub fn judge<F: FnMut(&Judgement)>(
+    submission: &PostJob,
+    problem: &Problem,
+    language: &Language,
+    work_dir: &Path,
+    config: &config::Judge,
+    cancel: &AtomicBool,
+    mut progress: F,
+) -> Judgement {
+    let mut judgement = Judgement::waiting(problem);
+    judgement.result = JudgeResult::Running;
+    judgement.cases[0].result = JudgeResult::Running;
+    progress(&judgement);
+
+    // # 创建评测目录与沙箱设置。程序与编译器都在评测目录中运行，评测目录使用绝对路径
+    if let Err(err) = fs::create_dir_all(work_dir) {
+        let info = format!("cannot create {}: {}", work_dir.display(), err);
+        return system_error(judgement, 0, info, work_dir);
+    }
+    let work_dir: &Path = &match fs::canonicalize(work_dir) {
+        Ok(work_dir) => work_dir,
+        Err(err) => {
+            let info = format!("cannot resolve {}: {}", work_dir.display(), err);
+            return system_error(judgement, 0, info, work_dir);
+        }
+    };
+    let policy = match Policy::new(&config.sandbox, problem, work_dir) {
+        Ok(policy) => policy,
+        Err(err) => {
+            let info = format!("cannot set up sandbox: {}", err);
+            return system_error(judgement, 0, info, work_dir);
+        }
+    };
+
+    // # 编译
+    let compiled = compile(
+        &submission.source_code,
+        language,
+        work_dir,
+        config,
+        &policy,
+        cancel,
+    );
+    let compiled = match compiled {
+        Ok((compiled, info)) => {
+            judgement.cases[0].info = info;
+            compiled
+        }
+        Err(info) => return system_error(judgement, 0, info, work_dir),
+    };
+    if cancel.load(Ordering::Relaxed) {
+        return canceled(judgement, work_dir);
+    }
+    if !compiled {
+        // 编译失败，其余测试点保持等待状态
+        judgement.cases[0].result = JudgeResult::CompilationError;
+        judgement.result = JudgeResult::CompilationError;
+        remove_work_dir(work_dir);
+        return judgement;
+    }
+    judgement.cases[0].result = JudgeResult::CompilationSuccess;
+    progress(&judgement);
+
+    // # 评测各个测试点：至多 `config.parallel_cases` 个测试点同时运行（还受所有评测线程共用的
+    // `config.max_running_cases` 与空闲核心数限制），结果按照测试点顺序汇总，与依次评测的结果相同
+    let case_count = problem.cases.len();
+    let mut threads = config.parallel_cases.max(1).min(case_count.max(1));
+    if config.max_running_cases != 0 {
+        threads = threads.min(config.max_running_cases);
+    }
+    if !config.cpus.is_empty() {
+        threads = threads.min(config.cpus.len());
+    }
+    let mut job_result = JudgeResult::Accepted; // 整体测评结果，取第一个未通过的测试点的结果
+
+    // 打包评测：同一组的测试点全部通过才得分，组内有测试点未通过时跳过该组剩余的测试点。
+    // 测试点部分正确时不跳过，整组按组内最低的得分比例得分
+    let groups = case_groups(problem);
+    let case_group: Vec<usize> = (0..case_count)
+        .map(|i| groups.iter().position(|x| x.contains(&i)).unwrap())
+        .collect();
+    let mut group_failed = vec![false; groups.len()];
+    let mut group_ratio = vec![1.0f64; groups.len()];
+    let stop_on_failure = problem.misc.judging_policy == Some(JudgingPolicy::StopOnFailure);
+    let mut stopped = false;
+
+    // 评测线程之间共享：下一个要运行的测试点，以及已知最早的未通过测试点（全部的与每组的），
+    // 按顺序评测时会被跳过的测试点不必运行
+    let next_case = AtomicUsize::new(0);
+    let first_failure = AtomicUsize::new(usize::MAX);
+    let group_first_failure: Vec<AtomicUsize> = (0..groups.len())
+        .map(|_| AtomicUsize::new(usize::MAX))
+        .collect();
+    // 汇总时发现评测系统出错，其余测试点不再运行
+    let abort = AtomicBool::new(false);
+
+    let error = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next_case, first_failure, group_first_failure, abort) =
+                (&next_case, &first_failure, &group_first_failure, &abort);
+            let (case_group, policy) = (&case_group, &policy);
+            scope.spawn(move || loop {
+                let i = next_case.fetch_add(1, Ordering::SeqCst);
+                if i >= case_count || abort.load(Ordering::SeqCst) || cancel.load(Ordering::Relaxed)
+                {
+                    break;
+                }
+                let group = case_group[i];
+                if (stop_on_failure && first_failure.load(Ordering::SeqCst) < i)
+                    || group_first_failure[group].load(Ordering::SeqCst) < i
+                {
+                    let _ = sender.send(CaseEvent::Finished(i, None));
+                    continue;
+                }
+                let cpu = match Cpu::acquire(config, cancel) {
+                    Some(cpu) => cpu,
+                    None => break,
+                };
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                let _ = sender.send(CaseEvent::Started(i));
+                let outcome = judge_case(work_dir, problem, i, language, policy, &cpu, cancel);
+                drop(cpu);
+                if let Ok(outcome) = &outcome {
+                    if outcome.result != JudgeResult::Accepted {
+                        first_failure.fetch_min(i, Ordering::SeqCst);
+                    }
+                    if outcome.score_ratio <= 0.0 {
+                        group_first_failure[group].fetch_min(i, Ordering::SeqCst);
+                    }
+                }
+                let _ = sender.send(CaseEvent::Finished(i, Some(outcome)));
+            });
+        }
+        drop(sender);
+
+        // 按测试点顺序汇总已经运行完的测试点，直到所有评测线程退出
+        let mut outcomes: Vec<Option<Option<Result<CaseOutcome, String>>>> =
+            (0..case_count).map(|_| None).collect();
+        let mut next = 0;
+        let mut error = None;
+        for event in receiver {
+            let (i, outcome) = match event {
+                CaseEvent::Started(i) => {
+                    judgement.cases[i + 1].result = JudgeResult::Running;
+                    progress(&judgement);
+                    continue;
+                }
+                CaseEvent::Finished(i, outcome) => (i, outcome),
+            };
+            outcomes[i] = Some(outcome);
+            while next < case_count && error.is_none() {
+                let outcome = match outcomes[next].take() {
+                    Some(outcome) => outcome,
+                    None => break,
+                };
+                // 运行或检查过程中被取消，该测试点没有结果
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                let (i, index, group) = (next, next + 1, case_group[next]);
+                next += 1;
+                let outcome = match outcome {
+                    Some(Ok(outcome)) if !stopped && !group_failed[group] => outcome,
+                    Some(Err(info)) if !stopped && !group_failed[group] => {
+                        abort.store(true, Ordering::SeqCst);
+                        error = Some((index, info));
+                        break;
+                    }
+                    _ => {
+                        judgement.cases[index].result = JudgeResult::Skipped;
+                        progress(&judgement);
+                        continue;
+                    }
+                };
+
+                if outcome.result != JudgeResult::Accepted {
+                    if job_result == JudgeResult::Accepted {
+                        job_result = outcome.result;
+                    }
+                    stopped = stop_on_failure;
+                }
+                if outcome.score_ratio > 0.0 {
+                    group_ratio[group] = group_ratio[group].min(outcome.score_ratio);
+                } else {
+                    group_failed[group] = true;
+                }
+                if !group_failed[group] && groups[group].iter().all(|&x| x <= i) {
+                    // 该组最后一个测试点通过或部分正确，整组按得分比例得分
+                    judgement.score += groups[group]
+                        .iter()
+                        .map(|&x| problem.cases[x].score)
+                        .sum::<f64>()
+                        * group_ratio[group]
+                        * correctness_ratio(problem);
+                }
+
+                let case = &mut judgement.cases[index];
+                case.result = outcome.result;
+                case.score_ratio = outcome.score_ratio;
+                case.info = outcome.info;
+                // 动态排名按墙上时间计算竞争得分，这类题目报告的 `time` 也是墙上时间，与排行榜一致
+                case.time = if problem.typ == "dynamic_ranking" {
+                    outcome.run_result.wall_time
+                } else {
+                    outcome.run_result.cpu_time
+                };
+                case.wall_time = outcome.run_result.wall_time;
+                case.memory = outcome.run_result.memory;
+                progress(&judgement);
+            }
+        }
+        error
+    });
+    if let Some((index, info)) = error {
+        return system_error(judgement, index, info, work_dir);
+    }
+    if cancel.load(Ordering::Relaxed) {
+        return canceled(judgement, work_dir);
+    }
+    // ^ 所有数据点测评完毕
+    judgement.result = job_result;
+
+    // 清理文件夹
+    remove_work_dir(work_dir);
+    judgement
+}
+
+/// 一个测试点运行与检查的结果
+struct CaseOutcome {
+    result: JudgeResult,
+    score_ratio: f64,
+    info: String,
+    run_result: RunResult,
+}
+
+/// 评测线程报告给汇总结果的线程的测试点状态，测试点下标从 0 开始
+enum CaseEvent {
+    /// 开始运行测试点
+    Started(usize),
+    /// 测试点运行结束；按顺序评测时会被跳过而没有运行时为 `None`，评测系统出错时为错误信息
+    Finished(usize, Option<Result<CaseOutcome, String>>),
+}
+
+/// 运行并检查第 `i` 个测试点（下标从 0 开始），返回测试点结果。
+///
+/// 程序只在分配到的 CPU 核心（如果有）上运行。
+fn judge_case(
+    work_dir: &Path,
+    problem: &Problem,
+    i: usize,
+    language: &Language,
+    policy: &Policy,
+    cpu: &Cpu,
+    cancel: &AtomicBool,
+) -> Result<CaseOutcome, String> {
+    let case_dir = case_dir(work_dir, i);
+    fs::create_dir_all(&case_dir)
+        .map_err(|err| format!("cannot create {}: {}", case_dir.display(), err))?;
+    let (run_result, interaction) = run_case(work_dir, problem, i, language, policy, cpu, cancel)?;
+    // 写入超出限制时程序一般被 SIGXFSZ 终止，忽略该信号的程序写入会失败，输出也不会超过限制
+    let output_size = fs::metadata(case_dir.join("test.out")).map_or(0, |x| x.len());
+
+    // 错误处理: 超出限制时程序被评测程序杀死，否则根据返回值或终止程序的信号判断
+    let verdict = if run_result.memory_limit_exceeded {
+        Verdict::new(JudgeResult::MemoryLimitExceeded, String::new())
+    } else if run_result.thread_limit_exceeded {
+        Verdict::new(JudgeResult::RuntimeError, "too many threads".to_string())
+    } else if run_result.time_limit_exceeded || run_result.signal == Some(libc::SIGXCPU) {
+        // 沙箱的 CPU 时间限制同样属于超时
+        Verdict::new(JudgeResult::TimeLimitExceeded, String::new())
+    } else if run_result.wall_time_limit_exceeded {
+        // 超出墙上时间限制时 CPU 时间仍未超限，程序大部分时间处于空闲状态（如等待输入）
+        Verdict::new(JudgeResult::IdlenessLimitExceeded, String::new())
+    } else if run_result.signal == Some(libc::SIGXFSZ) || output_size > output_limit(problem) {
+        Verdict::new(JudgeResult::OutputLimitExceeded, String::new())
+    } else if run_result.code == Some(0)
+        || (interaction.is_some() && run_result.signal == Some(libc::SIGPIPE))
+    {
+        // # 运行成功，检查test.out；交互题的结果由交互程序给出。
+        // 交互程序先结束时程序可能因管道关闭被 SIGPIPE 终止，此时同样以交互程序的结果为准；
+        // 程序以其他方式异常退出时结果为 `Runtime Error`，不受交互程序的结果影响
+        match interaction {
+            Some(verdict) => verdict,
+            None => check(problem, &problem.cases[i], &case_dir, cancel),
+        }
+    } else {
+        let info = match (run_result.code, run_result.signal) {
+            (Some(code), _) => format!("exited with code {}", code),
+            (None, Some(signal)) => format!("killed by signal {}", signal_name(signal)),
+            (None, None) => "terminated abnormally".to_string(),
+        };
+        Verdict::new(JudgeResult::RuntimeError, info)
+    };
+    Ok(CaseOutcome {
+        result: verdict.result,
+        score_ratio: verdict.score_ratio,
+        info: verdict.info,
+        run_result,
+    })
+}
+
+/// 第 `i` 个测试点（下标从 0 开始）的输出等文件所在的目录，各个测试点互不干扰
+fn case_dir(work_dir: &Path, i: usize) -> PathBuf {
+    work_dir.join(format!("case-{}", i + 1))
+}
+
+/// 评测被取消：尚未完成的编译和测试点标记为跳过，并清理 `work_dir`
+fn canceled(mut judgement: Judgement, work_dir: &Path) -> Judgement {
+    for case in judgement.cases.iter_mut() {
+        if case.result == JudgeResult::Waiting || case.result == JudgeResult::Running {
+            case.result = JudgeResult::Skipped;
+        }
+    }
+    judgement.result = JudgeResult::Skipped;
+    remove_work_dir(work_dir);
+    judgement
+}
+
+/// 评测系统出错：`cases[index]` 与整体结果为 `System Error`，其余测试点保持等待状态，并清理 `work_dir`
+fn system_error(
+    mut judgement: Judgement,
+    index: usize,
+    info: String,
+    work_dir: &Path,
+) -> Judgement {
+    log::error!("system error: {}", info);
+    // 同时运行的其他测试点没有结果
+    for case in judgement.cases.iter_mut() {
+        if case.result == JudgeResult::Running {
+            case.result = JudgeResult::Waiting;
+        }
+    }
+    judgement.cases[index].result = JudgeResult::SystemError;
+    judgement.cases[index].info = info;
+    judgement.result = JudgeResult::SystemError;
+    remove_work_dir(work_dir);
+    judgement
+}
+
+/// 删除 `work_dir`，失败时只记录日志，不影响评测结果
+fn remove_work_dir(work_dir: &Path) {
+    if let Err(err) = fs::remove_dir_all(work_dir) {
+        if err.kind() != io::ErrorKind::NotFound {
+            log::warn!("cannot remove {}: {}", work_dir.display(), err);
+        }
+    }
+}
+
+/// 信号的名称，如 `SIGSEGV (11)`
+fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGILL => "SIGILL",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGSYS => "SIGSYS",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return format!("{}", signal),
+    };
+    format!("{} ({})", name, signal)
+}
+
+/// 题目的输出大小限制（字节）
+fn output_limit(problem: &Problem) -> u64 {
+    problem.misc.output_limit.unwrap_or(DEFAULT_OUTPUT_LIMIT)
+}
+
+/// 测试点分数中按正确性给分的比例。
+///
+/// `dynamic_ranking` 类型的题目中，评测只给出 `1 - dynamic_ranking_ratio` 的分数，
+/// 其余的竞争得分根据所有用户的运行时间在排行榜中计算。
+fn correctness_ratio(problem: &Problem) -> f64 {
+    if problem.typ == "dynamic_ranking" {
+        1.0 - problem.misc.dynamic_ranking_ratio.unwrap_or(0.0)
+    } else {
+        1.0
+    }
+}
+
+/// 按照 `misc.packing` 将测试点分组（下标从 0 开始），未被打包的测试点各自单独成组
+fn case_groups(problem: &Problem) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = problem
+        .misc
+        .packing
+        .iter()
+        .flatten()
+        .map(|group| {
+            group
+                .iter()
+                .filter(|&&x| x >= 1 && x <= problem.cases.len())
+                .map(|&x| x - 1)
+                .collect()
+        })
+        .collect();
+    for i in 0..problem.cases.len() {
+        if !groups.iter().any(|x| x.contains(&i)) {
+            groups.push(vec![i]);
+        }
+    }
+    groups
+}
+
+/// 将源代码写入 `work_dir` 并按照语言配置编译为 `work_dir/main`，返回是否编译成功与编译器的输出。
+///
+/// 语言没有配置编译命令（如解释型语言）时只写入源代码，视为编译成功。
+/// 编译器的标准输出和标准错误一起截断到 `config.max_compile_output` 字节；超出语言的编译时间限制时编译失败。
+/// 编译成功的产物按照 `config.compile_cache` 缓存，相同的语言和源代码再次评测时直接使用缓存。
+/// 编译器按照 `policy` 在沙箱中运行，与提交的程序一样无法读取其他提交和题目数据。
+/// 无法写入源代码或无法启动编译器时返回错误信息。
+fn compile(
+    source_code: &str,
+    language: &Language,
+    work_dir: &Path,
+    config: &config::Judge,
+    policy: &Policy,
+    cancel: &AtomicBool,
+) -> Result<(bool, String), String> {
+    // # 创建源代码文件  e.g. main.rs
+    let src_file = work_dir.join(&language.file_name);
+    fs::write(&src_file, source_code)
+        .map_err(|err| format!("cannot write {}: {}", src_file.display(), err))?;
+    // ^ 源码写入完毕
+
+    // get language-specific commands
+    let commands = expand_command(&language.command, work_dir, language);
+    if commands.is_empty() {
+        return Ok((true, String::new()));
+    }
+    let cache_key = cache::key(language, source_code);
+    if let Some(info) = cache::restore(&config.compile_cache, &cache_key, work_dir) {
+        return Ok((true, info));
+    }
+
+    // 编译器的标准输出和标准错误写入同一个文件
+    let output_path = work_dir.join("compile.out");
+    let output_file = File::create(&output_path)
+        .map_err(|err| format!("cannot create {}: {}", output_path.display(), err))?;
+    let error_file = output_file
+        .try_clone()
+        .map_err(|err| format!("cannot create {}: {}", output_path.display(), err))?;
+
+    // 子进程：在沙箱中编译，限制时间但不限制内存
+    let mut command = Command::new(&commands[0]);
+    command
+        .args(&commands[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(output_file))
+        .stderr(Stdio::from(error_file));
+    policy
+        .apply_compiler(&mut command)
+        .map_err(|err| format!("cannot set up sandbox: {}", err))?;
+    let limits = Limits {
+        time_limit: 0,
+        wall_time_limit: language.compile_time_limit,
+        memory_limit: 0,
+        output_limit: 0,
+        max_threads: 0,
+    };
+    let run_result = runner::run(&mut command, &limits, cancel)
+        .map_err(|err| format!("cannot run compiler {}: {}", commands[0], err))?;
+
+    let mut info = read_truncated(&output_path, config.max_compile_output);
+    if run_result.wall_time_limit_exceeded {
+        if !info.is_empty() && !info.ends_with('\n') {
+            info.push('\n');
+        }
+        info.push_str("Compilation time limit exceeded.");
+    }
+    let compiled = run_result.code == Some(0);
+    if compiled {
+        let exclude = [language.file_name.as_str(), "compile.out"];
+        cache::store(&config.compile_cache, &cache_key, work_dir, &exclude, &info);
+    }
+    Ok((compiled, info))
+}
+
+/// 替换命令模板中的占位符：`%OUTPUT%` 为 `work_dir/main`，`%INPUT%` 为源代码文件，`%DIR%` 为 `work_dir`
+fn expand_command(template: &[String], work_dir: &Path, language: &Language) -> Vec<String> {
+    template
+        .iter()
+        .map(|x| match x.as_str() {
+            "%OUTPUT%" => work_dir.join("main").to_string_lossy().into_owned(),
+            "%INPUT%" => work_dir
+                .join(&language.file_name)
+                .to_string_lossy()
+                .into_owned(),
+            "%DIR%" => work_dir.to_string_lossy().into_owned(),
+            _ => x.to_string(),
+        })
+        .collect()
+}
+
+/// 把运行命令中的 `%MEMORY%` 替换为内存限制（KB），如 Java 的 `-Xmx%MEMORY%k`。
+/// 不限制内存时省略含有 `%MEMORY%` 的参数
+fn expand_memory(command: Vec<String>, memory_limit: u64) -> Vec<String> {
+    command
+        .into_iter()
+        .filter(|x| memory_limit != 0 || !x.contains("%MEMORY%"))
+        .map(|x| x.replace("%MEMORY%", &(memory_limit / 1024).to_string()))
+        .collect()
+}
+
+/// 读取文件开头至多 `limit` 字节，文件更长时在末尾注明已截断
+fn read_truncated(path: &Path, limit: usize) -> String {
+    let mut content = Vec::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return String::new(),
+    };
+    // 多读一个字节以判断是否被截断
+    if file
+        .take(limit as u64 + 1)
+        .read_to_end(&mut content)
+        .is_err()
+    {
+        return String::new();
+    }
+    let truncated = content.len() > limit;
+    content.truncate(limit);
+    let mut text = String::from_utf8_lossy(&content).into_owned();
+    if truncated {
+        text.push_str("\n(output truncated)");
+    }
+    text
+}
+
+/// 以第 `i` 个测试点的输入文件为标准输入，在沙箱中按照语言的运行命令（默认为 `work_dir/main`）运行程序，
+/// 输出写入测试点目录中的 `test.out`，大小不超过题目的输出大小限制。时间和内存限制按照语言的倍率放大。
+/// 程序只在分配到的 CPU 核心（如果有）上运行。
+///
+/// `interactive` 类型的题目中程序与交互程序通过管道交互，同时返回交互程序给出的测试点结果与附加信息。
+/// 无法打开测试数据或无法启动程序时返回错误信息。
+fn run_case(
+    work_dir: &Path,
+    problem: &Problem,
+    i: usize,
+    language: &Language,
+    policy: &Policy,
+    cpu: &Cpu,
+    cancel: &AtomicBool,
+) -> Result<(RunResult, Option<Verdict>), String> {
+    let problem_case = &problem.cases[i];
+    let case_dir = case_dir(work_dir, i);
+    let limits = Limits {
+        time_limit: (problem_case.time_limit as f64 * language.time_multiplier) as u64,
+        wall_time_limit: (problem_case.wall_time_limit() as f64 * language.time_multiplier) as u64,
+        memory_limit: (problem_case.memory_limit as f64 * language.memory_multiplier) as u64,
+        output_limit: output_limit(problem),
+        max_threads: policy.max_threads(),
+    };
+    let commands = match &language.run {
+        Some(run) => expand_memory(expand_command(run, work_dir, language), limits.memory_limit),
+        None => vec![work_dir.join("main").to_string_lossy().into_owned()],
+    };
+    if commands.is_empty() {
+        return Err(format!("no run command for language {}", language.name));
+    }
+
+    let mut command = Command::new(&commands[0]);
+    command.args(&commands[1..]);
+    runner::stop_at_exec(&mut command);
+    policy
+        .apply(&mut command, &limits)
+        .map_err(|err| format!("cannot set up sandbox: {}", err))?;
+    cpu.pin(&mut command);
+
+    if problem.typ == "interactive" {
+        let interactor_command = problem.misc.interactor.as_deref().unwrap_or_default();
+        let (run_result, verdict) = interactor::interact(
+            command,
+            &limits,
+            interactor_command,
+            &problem_case.input_file,
+            &problem_case.answer_file,
+            &case_dir,
+            cancel,
+        )?;
+        return Ok((run_result, Some(verdict)));
+    }
+
+    // 指定in out文件
+    let in_file = File::open(&problem_case.input_file)
+        .map_err(|err| format!("cannot open {}: {}", problem_case.input_file, err))?;
+    let out_path = case_dir.join("test.out");
+    let out_file = File::create(&out_path)
+        .map_err(|err| format!("cannot create {}: {}", out_path.display(), err))?;
+    command
+        .stdin(Stdio::from(in_file))
+        .stdout(Stdio::from(out_file))
+        .stderr(Stdio::null());
+    let run_result = runner::run(&mut command, &limits, cancel)
+        .map_err(|err| format!("cannot run program: {}", err))?;
+    Ok((run_result, None))
+}
+
+/// 检查程序输出 `case_dir/test.out`，返回测试点结果、得分比例与附加信息。
+///
+/// 内置的比较方式只在题目设置了 `misc.partial_score` 时给出部分分
+fn check(
+    problem: &Problem,
+    problem_case: &config::Case,
+    case_dir: &Path,
+    cancel: &AtomicBool,
+) -> Verdict {
+    let output_file = case_dir.join("test.out");
+    if problem.typ == "spj" {
+        let command = problem.misc.special_judge.as_deref().unwrap_or_default();
+        return spj::special_judge(
+            command,
+            &problem_case.input_file,
+            &output_file,
+            &problem_case.answer_file,
+            case_dir,
+            cancel,
+        );
+    }
+
+    // 比较test.out & file.ans
+    let out_file = match File::open(&output_file) {
+        Ok(file) => file,
+        Err(err) => {
+            let info = format!("cannot read {}: {}", output_file.display(), err);
+            return Verdict::new(JudgeResult::SystemError, info);
+        }
+    };
+    let ans_file = match File::open(&problem_case.answer_file) {
+        Ok(file) => file,
+        Err(err) => {
+            let info = format!("cannot read {}: {}", problem_case.answer_file, err);
+            return Verdict::new(JudgeResult::SystemError, info);
+        }
+    };
+    let comparator = problem
+        .misc
+        .comparator
+        .unwrap_or(if problem.typ == "strict" {
+            Comparator::Strict
+        } else {
+            Comparator::Lines
+        });
+    match compare::compare(
+        comparator,
+        problem.misc.epsilon,
+        problem.misc.partial_score.unwrap_or(false),
+        BufReader::new(out_file),
+        BufReader::new(ans_file),
+    ) {
+        Ok(ratio) => Verdict::partial(ratio, String::new()),
+        Err(err) => Verdict::new(
+            JudgeResult::SystemError,
+            format!("cannot compare output: {}", err),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Case as ProblemCase;
+
+    fn rust() -> Language {
+        Language {
+            name: "Rust".to_string(),
+            file_name: "main.rs".to_string(),
+            command: ["rustc", "-C", "opt-level=2", "-o", "%OUTPUT%", "%INPUT%"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            run: None,
+            compile_time_limit: 0,
+            time_multiplier: 1.0,
+            memory_multiplier: 1.0,
+        }
+    }
+
+    fn aplusb(typ: &str) -> Problem {
+        Problem {
+            id: 0,
+            name: "aplusb".to_string(),
+            typ: typ.to_string(),
+            misc: serde_json::from_str("{}").unwrap(),
+            cases: (1..=2)
+                .map(|i| ProblemCase {
+                    score: 50.0,
+                    input_file: format!("./tests/data/aplusb/{}.in", i),
+                    answer_file: format!("./tests/data/aplusb/{}.ans", i),
+                    time_limit: 2000000,
+                    wall_time_limit: None,
+                    memory_limit: 0,
+                })
+                .collect(),
+        }
+    }
+
+    fn submit(source_code: &str) -> PostJob {
+        PostJob {
+            source_code: source_code.to_string(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+        }
+    }
+
+    /// 测试使用的评测配置：不使用编译缓存，避免在默认缓存目录中留下文件；
+    /// 沙箱不可用时跳过沙箱，使不测试沙箱的用例在任何系统上都能运行
+    fn judge_config() -> config::Judge {
+        let mut config = config::Judge::default();
+        config.compile_cache.enabled = false;
+        config.sandbox.required = false;
+        config
+    }
+
+    fn run(name: &str, problem: &Problem, source_code: &str) -> Judgement {
+        run_with_config(name, problem, source_code, &judge_config())
+    }
+
+    fn run_with_config(
+        name: &str,
+        problem: &Problem,
+        source_code: &str,
+        config: &config::Judge,
+    ) -> Judgement {
+        let work_dir = Path::new("TMPDIR").join(format!("judge-test-{}", name));
+        let cancel = AtomicBool::new(false);
+        judge(
+            &submit(source_code),
+            problem,
+            &rust(),
+            &work_dir,
+            config,
+            &cancel,
+            |_| {},
+        )
+    }
+
+    const APLUSB: &str = "fn main() { let mut s = String::new(); std::io::stdin().read_line(&mut s).unwrap(); let a: i32 = s.trim().parse().unwrap(); s.clear(); std::io::stdin().read_line(&mut s).unwrap(); let b: i32 = s.trim().parse().unwrap(); println!(\"{}\", a + b); }";
+
+    #[test]
+    fn accepted() {
+        let judgement = run("accepted", &aplusb("standard"), APLUSB);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert_eq!(judgement.score, 100.0);
+        assert_eq!(judgement.cases[0].result, JudgeResult::CompilationSuccess);
+        assert!(judgement.cases[1..]
+            .iter()
+            .all(|x| x.result == JudgeResult::Accepted));
+    }
+
+    #[test]
+    fn wrong_answer() {
+        let judgement = run(
+            "wrong_answer",
+            &aplusb("standard"),
+            "fn main() { println!(\"0\"); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.score, 0.0);
+    }
+
+    #[test]
+    fn runtime_error() {
+        let problem = aplusb("standard");
+        let judgement = run("panic", &problem, "fn main() { panic!(); }");
+        assert_eq!(judgement.result, JudgeResult::RuntimeError);
+        assert_eq!(judgement.cases[1].info, "exited with code 101");
+
+        let judgement = run("exit", &problem, "fn main() { std::process::exit(3); }");
+        assert_eq!(judgement.result, JudgeResult::RuntimeError);
+        assert_eq!(judgement.cases[1].info, "exited with code 3");
+
+        let judgement = run(
+            "segfault",
+            &problem,
+            "fn main() { unsafe { std::ptr::null_mut::<i32>().write_volatile(1) } }",
+        );
+        assert_eq!(judgement.result, JudgeResult::RuntimeError);
+        assert_eq!(judgement.cases[1].info, "killed by signal SIGSEGV (11)");
+    }
+
+    #[test]
+    fn time_limits() {
+        let mut problem = aplusb("standard");
+        problem.cases.truncate(1);
+        problem.cases[0].time_limit = 200_000;
+
+        // 等待的时间只计入墙上时间，不计入 CPU 时间
+        let source_code = APLUSB.replace(
+            "println!",
+            "std::thread::sleep(std::time::Duration::from_millis(300)); println!",
+        );
+        let judgement = run("sleep", &problem, &source_code);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert!(judgement.cases[1].time < 100_000);
+        assert!(judgement.cases[1].wall_time >= 300_000);
+
+        let judgement = run("busy_loop", &problem, "fn main() { loop {} }");
+        assert_eq!(judgement.result, JudgeResult::TimeLimitExceeded);
+
+        // 一直等待输入的程序超出墙上时间限制
+        problem.cases[0].wall_time_limit = Some(500_000);
+        let judgement = run(
+            "idle",
+            &problem,
+            "fn main() { std::thread::sleep(std::time::Duration::from_secs(10)); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::IdlenessLimitExceeded);
+        assert!(judgement.cases[1].wall_time < 2_000_000);
+    }
+
+    #[test]
+    fn strict_compare() {
+        // 多输出一个空行，standard 模式下通过，strict 模式下不通过
+        let source_code = APLUSB.replace("println!(\"{}\", a + b);", "println!(\"{}\\n\", a + b);");
+        let judgement = run("standard", &aplusb("standard"), &source_code);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        let judgement = run("strict", &aplusb("strict"), &source_code);
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+    }
+
+    #[test]
+    fn comparators() {
+        let check = |comparator: Comparator, out: &str, ans: &str| {
+            compare::compare(comparator, None, false, out.as_bytes(), ans.as_bytes()).unwrap()
+                == 1.0
+        };
+        assert!(check(Comparator::Tokens, "1  2\n3\n\n", "1 2 3"));
+        assert!(!check(Comparator::Tokens, "1 2 3 4", "1 2 3"));
+        assert!(check(
+            Comparator::Float,
+            "3.1415927 1e6",
+            "3.14159265 1000000.5"
+        ));
+        assert!(!check(Comparator::Float, "3.1416", "3.14159265"));
+        assert!(!check(Comparator::Float, "nan", "0"));
+        assert!(check(Comparator::CaseInsensitive, "YES\nno\n", "yes\nNo"));
+        assert!(!check(Comparator::CaseInsensitive, "YES NO", "yes\nno"));
+        assert!(check(Comparator::UnorderedLines, "b\na\nb\n", "b\nb\na\n"));
+        assert!(!check(Comparator::UnorderedLines, "a\na\nb\n", "a\nb\nb\n"));
+        assert!(check(Comparator::Lines, "\n a  b \n\nc", "a  b\n\nc\n\n"));
+        assert!(!check(Comparator::Lines, "a b\nc", "a  b\nc"));
+        assert!(!check(Comparator::Lines, "a\nb", "a\n\nb"));
+        assert!(!check(Comparator::Lines, "ab", "a"));
+        assert!(check(Comparator::CaseInsensitive, "ÄB c", "äb c  "));
+
+        // 通过 misc 指定比较方式
+        let mut problem = aplusb("standard");
+        problem.misc.comparator = Some(Comparator::Float);
+        problem.misc.epsilon = Some(0.1);
+        let source_code = APLUSB.replace("println!(\"{}\", a + b);", "println!(\"{}.05\", a + b);");
+        let judgement = run("float", &problem, &source_code);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        problem.misc.comparator = Some(Comparator::Tokens);
+        let judgement = run("tokens", &problem, &source_code);
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+    }
+
+    #[test]
+    fn memory_limit_exceeded() {
+        let mut problem = aplusb("standard");
+        for case in problem.cases.iter_mut() {
+            case.memory_limit = 16 * 1024 * 1024;
+        }
+        // 申请并写满 64MB 内存
+        let judgement = run(
+            "memory_limit_exceeded",
+            &problem,
+            "fn main() { let v = vec![1u8; 64 << 20]; println!(\"{}\", v.iter().map(|&x| x as u64).sum::<u64>()); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::MemoryLimitExceeded);
+        assert_eq!(judgement.cases[1].result, JudgeResult::MemoryLimitExceeded);
+
+        // 申请后只读取而不写入的全零内存不占用物理内存，但同样计入内存用量
+        let judgement = run(
+            "memory_limit_zeroed",
+            &problem,
+            "fn main() { let v = std::hint::black_box(vec![0u8; 64 << 20]); println!(\"{}\", v.iter().map(|&x| x as u64).sum::<u64>()); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::MemoryLimitExceeded);
+
+        // 地址空间有内核保证的硬上限，无法一次申请远超内存限制的内存
+        let source_code = aplusb_if("Vec::<u8>::new().try_reserve(4 << 30).is_err()");
+        let judgement = run("memory_limit_address_space", &problem, &source_code);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+    }
+
+    #[test]
+    fn memory_usage() {
+        // 运行时间极短的程序也能统计到内存峰值
+        let judgement = run("memory_usage", &aplusb("standard"), APLUSB);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert!(judgement.cases[1..].iter().all(|x| x.memory > 0));
+
+        let source_code = APLUSB.replace(
+            "println!(\"{}\", a + b);",
+            "let v = std::hint::black_box(vec![1u8; 32 << 20]); println!(\"{}\", a + b + v.len() as i32 - (32 << 20));",
+        );
+        let judgement = run("memory_usage_peak", &aplusb("standard"), &source_code);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert!(judgement.cases[1..].iter().all(|x| x.memory >= 32 << 20));
+    }
+
+    #[test]
+    fn memory_placeholder() {
+        let command = vec![
+            "java".to_string(),
+            "-Xmx%MEMORY%k".to_string(),
+            "Main".to_string(),
+        ];
+        assert_eq!(
+            expand_memory(command.clone(), 256 << 20),
+            ["java", "-Xmx262144k", "Main"]
+        );
+        assert_eq!(expand_memory(command, 0), ["java", "Main"]);
+    }
+
+    #[test]
+    fn output_limit_exceeded() {
+        let mut problem = aplusb("standard");
+        problem.misc.output_limit = Some(1 << 20);
+        let source_code = "fn main() { loop { println!(\"Hello World!\"); } }";
+        let judgement = run("output_limit_exceeded", &problem, source_code);
+        assert_eq!(judgement.result, JudgeResult::OutputLimitExceeded);
+
+        // 关闭沙箱时同样限制输出大小
+        let config = config::Judge {
+            sandbox: config::Sandbox {
+                enabled: false,
+                ..config::Sandbox::default()
+            },
+            ..judge_config()
+        };
+        let judgement = judge(
+            &submit(source_code),
+            &problem,
+            &rust(),
+            &Path::new("TMPDIR").join("judge-test-output_limit_no_sandbox"),
+            &config,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(judgement.result, JudgeResult::OutputLimitExceeded);
+    }
+
+    #[test]
+    fn output_limit_above_max_file_size() {
+        // 输出大小只受题目的输出大小限制，不受沙箱的文件大小限制
+        let mut problem = aplusb("standard");
+        problem.misc.output_limit = Some(2 << 20);
+        let config = config::Judge {
+            sandbox: config::Sandbox {
+                max_file_size: 1 << 20,
+                ..config::Sandbox::default()
+            },
+            ..judge_config()
+        };
+        let source_code = APLUSB.replace(
+            "println!(\"{}\", a + b);",
+            "println!(\"{}\", \" \".repeat(3 << 19)); println!(\"{}\", a + b);",
+        );
+        let judgement = run_with_config(
+            "output_limit_above_max_file_size",
+            &problem,
+            &source_code,
+            &config,
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+    }
+
+    #[test]
+    fn stop_on_failure() {
+        let mut problem = aplusb("standard");
+        let source_code = "fn main() { println!(\"0\"); }";
+        let judgement = run("run_all", &problem, source_code);
+        assert!(judgement.cases[1..]
+            .iter()
+            .all(|x| x.result == JudgeResult::WrongAnswer));
+
+        problem.misc.judging_policy = Some(JudgingPolicy::StopOnFailure);
+        let judgement = run("stop_on_failure", &problem, source_code);
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.cases[1].result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.cases[2].result, JudgeResult::Skipped);
+    }
+
+    #[test]
+    fn parallel_cases() {
+        let mut problem = aplusb("standard");
+        problem.cases = (1..=10)
+            .map(|i| ProblemCase {
+                score: 10.0,
+                input_file: format!("./tests/data/aplusb/{}.in", i),
+                answer_file: format!("./tests/data/aplusb/{}.ans", i),
+                time_limit: 2000000,
+                wall_time_limit: None,
+                memory_limit: 0,
+            })
+            .collect();
+        let config = config::Judge {
+            parallel_cases: 4,
+            ..Default::default()
+        };
+        let judgement = run_with_config("parallel", &problem, APLUSB, &config);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert_eq!(judgement.score, 100.0);
+
+        // 结果与依次评测相同：第一个未通过的测试点之后都被跳过
+        problem.misc.judging_policy = Some(JudgingPolicy::StopOnFailure);
+        let source_code = APLUSB.replace("a + b", "if a + b > 10000 { 0 } else { a + b }");
+        let sequential = run("sequential_stop", &problem, &source_code);
+        let parallel = run_with_config("parallel_stop", &problem, &source_code, &config);
+        let results =
+            |judgement: &Judgement| judgement.cases.iter().map(|x| x.result).collect::<Vec<_>>();
+        assert_eq!(results(&parallel), results(&sequential));
+        assert_eq!(parallel.score, sequential.score);
+
+        // 设置了核心时程序只在分配到的核心上运行
+        problem.misc.judging_policy = None;
+        problem.cases.truncate(3);
+        let config = config::Judge {
+            parallel_cases: 4,
+            cpus: vec![0],
+            ..Default::default()
+        };
+        let source_code = APLUSB.replace(
+            "a + b",
+            "if std::fs::read_to_string(\"/proc/self/status\").unwrap().contains(\"Cpus_allowed_list:\\t0\\n\") { a + b } else { 0 }",
+        );
+        let judgement = run_with_config("pinned", &problem, &source_code, &config);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+    }
+
+    #[test]
+    fn dynamic_ranking() {
+        // 评测只给出正确性部分的分数，竞争得分在排行榜中计算
+        let mut problem = aplusb("dynamic_ranking");
+        problem.misc.dynamic_ranking_ratio = Some(0.3);
+        let judgement = run("dynamic_ranking", &problem, APLUSB);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert!((judgement.score - 70.0).abs() < 1e-9);
+        assert!(judgement.cases[1..].iter().all(|x| x.time == x.wall_time));
+    }
+
+    #[test]
+    fn packed_judging() {
+        let mut problem = aplusb("standard");
+        problem.misc.packing = Some(vec![vec![1, 2]]);
+        let judgement = run("packed_accepted", &problem, APLUSB);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert_eq!(judgement.score, 100.0);
+
+        // 第一个测试点未通过，同组的第二个测试点被跳过
+        let judgement = run(
+            "packed_wrong_answer",
+            &problem,
+            "fn main() { println!(\"0\"); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.score, 0.0);
+        assert_eq!(judgement.cases[1].result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.cases[2].result, JudgeResult::Skipped);
+    }
+
+    fn guessing(special_judge: &str) -> Problem {
+        Problem {
+            id: 1,
+            name: "guessing".to_string(),
+            typ: "spj".to_string(),
+            misc: serde_json::from_str(&format!("{{\"special_judge\": {}}}", special_judge))
+                .unwrap(),
+            cases: vec![ProblemCase {
+                score: 100.0,
+                input_file: "./tests/data/guessing/1.in".to_string(),
+                answer_file: "./tests/data/guessing/1.ans".to_string(),
+                time_limit: 2000000,
+                wall_time_limit: None,
+                memory_limit: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn special_judge() {
+        let problem =
+            guessing(r#"["python3", "./tests/data/guessing/judge.py", "%OUTPUT%", "%ANSWER%"]"#);
+        let judgement = run(
+            "spj_accepted",
+            &problem,
+            "fn main() { println!(\"{}\", 99.0); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert_eq!(judgement.cases[1].info, "The error is within bounds");
+        let judgement = run(
+            "spj_wrong_answer",
+            &problem,
+            "fn main() { println!(\"{}\", 80.0); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.cases[1].info, "The error is beyond bounds");
+
+        // special judge 异常退出或输出无法识别
+        let judgement = run("spj_crash", &guessing(r#"["false"]"#), "fn main() {}");
+        assert_eq!(judgement.result, JudgeResult::SPJError);
+        let judgement = run(
+            "spj_garbage",
+            &guessing(r#"["echo", "OK"]"#),
+            "fn main() {}",
+        );
+        assert_eq!(judgement.result, JudgeResult::SPJError);
+    }
+
+    #[test]
+    fn compare_long_lines() {
+        use std::io::{BufRead, BufReader, Read};
+        // 很长的一行或一个单词按字符流式比较
+        fn long(last: &'static [u8]) -> impl BufRead {
+            BufReader::new(io::repeat(b'a').take(4 << 20).chain(last))
+        }
+        let check = |comparator: Comparator, out: &'static [u8], ans: &'static [u8]| {
+            compare::compare(comparator, None, false, long(out), long(ans)).unwrap() == 1.0
+        };
+        for comparator in [
+            Comparator::Lines,
+            Comparator::Tokens,
+            Comparator::CaseInsensitive,
+            Comparator::UnorderedLines,
+        ] {
+            assert!(check(comparator, b"b \n", b"b"));
+            assert!(!check(comparator, b"b", b"c"));
+            assert!(!check(comparator, b"b", b"bb"));
+        }
+        assert!(!check(Comparator::UnorderedLines, b"b", b"\nb"));
+    }
+
+    #[test]
+    fn partial_score() {
+        let ratio = |comparator: Comparator, out: &str, ans: &str| {
+            compare::compare(comparator, None, true, out.as_bytes(), ans.as_bytes()).unwrap()
+        };
+        assert_eq!(ratio(Comparator::Tokens, "1 2 4", "1 2 3 4"), 0.5);
+        assert_eq!(ratio(Comparator::Lines, "a\nb\nc\nd\n", "a\nx\nc"), 0.5);
+        assert_eq!(
+            ratio(Comparator::UnorderedLines, "c\na\n", "a\nb\nc\nd"),
+            0.5
+        );
+        assert_eq!(ratio(Comparator::Strict, "a\nb", "a\nc"), 0.0);
+
+        // special judge 给出得分比例，打包的测试点按组内最低的比例得分
+        let mut problem = guessing(r#"["printf", "0.25\\nclose\\n"]"#);
+        let judgement = run("spj_partial", &problem, "fn main() {}");
+        assert_eq!(judgement.result, JudgeResult::PartiallyCorrect);
+        assert_eq!(judgement.score, 25.0);
+        assert_eq!(judgement.cases[1].score_ratio, 0.25);
+        assert_eq!(judgement.cases[1].info, "close");
+
+        problem.cases.push(problem.cases[0].clone());
+        problem.cases[1].answer_file = "./tests/data/hello_world/1.ans".to_string();
+        problem.misc.packing = Some(vec![vec![1, 2]]);
+        problem.misc.special_judge = Some(
+            [
+                "sh",
+                "-c",
+                "case $0 in *guessing*) echo 0.5;; *) echo 0.75;; esac",
+                "%ANSWER%",
+            ]
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+        );
+        let judgement = run("spj_partial_packing", &problem, "fn main() {}");
+        assert_eq!(judgement.result, JudgeResult::PartiallyCorrect);
+        assert_eq!(judgement.score, 100.0);
+
+        let judgement = run(
+            "spj_invalid_ratio",
+            &guessing(r#"["echo", "1.5"]"#),
+            "fn main() {}",
+        );
+        assert_eq!(judgement.result, JudgeResult::SPJError);
+    }
+
+    fn interactive() -> Problem {
+        Problem {
+            id: 2,
+            name: "interactive".to_string(),
+            typ: "interactive".to_string(),
+            misc: serde_json::from_str(
+                r#"{"interactor": ["python3", "./tests/data/interactive/interactor.py", "%INPUT%", "%ANSWER%"]}"#,
+            )
+            .unwrap(),
+            cases: vec![ProblemCase {
+                score: 100.0,
+                input_file: "./tests/data/interactive/1.in".to_string(),
+                answer_file: "./tests/data/interactive/1.ans".to_string(),
+                time_limit: 2000000,
+                wall_time_limit: None,
+                memory_limit: 0,
+            }],
+        }
+    }
+
+    /// 二分猜数的程序
+    const BINARY_SEARCH: &str = "use std::io::*; fn main() { let mut s = String::new(); stdin().read_line(&mut s).unwrap(); let (mut lo, mut hi) = (1, s.trim().parse::<i32>().unwrap()); loop { let mid = (lo + hi) / 2; println!(\"{}\", mid); stdout().flush().unwrap(); s.clear(); stdin().read_line(&mut s).unwrap(); match s.trim() { \"=\" => break, \"<\" => hi = mid - 1, _ => lo = mid + 1 } } }";
+
+    #[test]
+    fn interactive_problem() {
+        let problem = interactive();
+        let judgement = run("interactive", &problem, BINARY_SEARCH);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert!(judgement.cases[1].info.starts_with("Found the answer"));
+
+        // 线性查找猜测次数过多，交互程序结束后程序正常退出
+        let judgement = run(
+            "interactive_wrong_answer",
+            &problem,
+            "use std::io::*; fn main() { let mut s = String::new(); stdin().read_line(&mut s).unwrap(); let mut guess = 1; loop { if writeln!(stdout(), \"{}\", guess).is_err() || stdout().flush().is_err() { return; } s.clear(); if stdin().read_line(&mut s).unwrap_or(0) == 0 || s.trim() == \"=\" { return; } guess += 1; } }",
+        );
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+        assert_eq!(judgement.cases[1].info, "Too many guesses");
+
+        // 程序崩溃后交互程序读到文件结尾而判定答案错误，结果仍为程序的运行错误
+        let judgement = run(
+            "interactive_crash",
+            &problem,
+            "fn main() { let mut s = String::new(); std::io::stdin().read_line(&mut s).unwrap(); std::process::abort(); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::RuntimeError);
+        assert_eq!(judgement.cases[1].info, "killed by signal SIGABRT (6)");
+
+        // 交互程序被信号终止时，程序的运行错误不会变为 SPJ Error
+        let mut crashing = problem.clone();
+        crashing.misc.interactor = Some(
+            ["sh", "-c", "kill -9 $$"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+        );
+        let judgement = run(
+            "interactive_crash_interactor",
+            &crashing,
+            "fn main() { std::process::exit(3); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::RuntimeError);
+
+        // 程序不输出任何内容就等待输入，交互程序同样在等待输入
+        let mut problem = problem;
+        problem.cases[0].wall_time_limit = Some(500_000);
+        let judgement = run(
+            "interactive_idle",
+            &problem,
+            "fn main() { let mut s = String::new(); std::io::stdin().read_line(&mut s).unwrap(); std::io::stdin().read_line(&mut s).unwrap(); }",
+        );
+        assert_eq!(judgement.result, JudgeResult::IdlenessLimitExceeded);
+    }
+
+    #[test]
+    fn compilation_error() {
+        let mut updates = 0;
+        let judgement = judge(
+            &submit("fn main() {"),
+            &aplusb("standard"),
+            &rust(),
+            &Path::new("TMPDIR").join("judge-test-compilation_error"),
+            &judge_config(),
+            &AtomicBool::new(false),
+            |_| updates += 1,
+        );
+        assert_eq!(updates, 1);
+        assert_eq!(judgement.result, JudgeResult::CompilationError);
+        assert_eq!(judgement.cases[0].result, JudgeResult::CompilationError);
+        assert!(judgement.cases[0].info.contains("error"));
+        assert_eq!(judgement.cases[1].result, JudgeResult::Waiting);
+    }
+
+    #[test]
+    fn compiler_output() {
+        // 编译器输出被截断
+        let config = config::Judge {
+            max_compile_output: 16,
+            ..judge_config()
+        };
+        let judgement = judge(
+            &submit("fn main() {"),
+            &aplusb("standard"),
+            &rust(),
+            &Path::new("TMPDIR").join("judge-test-compiler_output"),
+            &config,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(judgement.result, JudgeResult::CompilationError);
+        assert!(judgement.cases[0].info.len() < 64);
+        assert!(judgement.cases[0].info.ends_with("(output truncated)"));
+
+        // 超出编译时间限制
+        let mut language = rust();
+        language.compile_time_limit = 1000;
+        let judgement = judge(
+            &submit(APLUSB),
+            &aplusb("standard"),
+            &language,
+            &Path::new("TMPDIR").join("judge-test-compile_time_limit"),
+            &judge_config(),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(judgement.result, JudgeResult::CompilationError);
+        assert!(judgement.cases[0]
+            .info
+            .ends_with("Compilation time limit exceeded."));
+    }
+
+    #[test]
+    fn compile_cache() {
+        let mut config = config::Judge::default();
+        config.compile_cache.dir = "TMPDIR/judge-test-compile-cache".to_string();
+        purge_compile_cache(&config).unwrap();
+        let problem = aplusb("standard");
+        let judgement = run_with_config("cache_miss", &problem, APLUSB, &config);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+
+        // 把缓存的程序换成输出错误答案的脚本，再次评测相同的代码时使用缓存而不重新编译
+        let main = Path::new(&config.compile_cache.dir)
+            .join(cache::key(&rust(), APLUSB))
+            .join("files/main");
+        fs::write(&main, "#!/bin/sh\necho 0\n").unwrap();
+        let judgement = run_with_config("cache_hit", &problem, APLUSB, &config);
+        assert_eq!(judgement.result, JudgeResult::WrongAnswer);
+
+        assert_eq!(purge_compile_cache(&config).unwrap(), 1);
+        let judgement = run_with_config("cache_purged", &problem, APLUSB, &config);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+
+        // 超出大小上限时删除最久未使用的缓存
+        config.compile_cache.max_size = 1;
+        let source_code = APLUSB.replace("a + b", "b + a");
+        let judgement = run_with_config("cache_evicted", &problem, &source_code, &config);
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert_eq!(purge_compile_cache(&config).unwrap(), 0);
+        fs::remove_dir_all(&config.compile_cache.dir).unwrap();
+    }
+
+    #[test]
+    fn system_error() {
+        // 无法启动编译器
+        let mut language = rust();
+        language.command[0] = "no-such-compiler".to_string();
+        let judgement = judge(
+            &submit(APLUSB),
+            &aplusb("standard"),
+            &language,
+            &Path::new("TMPDIR").join("judge-test-system_error_compile"),
+            &judge_config(),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(judgement.result, JudgeResult::SystemError);
+        assert_eq!(judgement.cases[0].result, JudgeResult::SystemError);
+        assert!(judgement.cases[0].info.contains("no-such-compiler"));
+
+        // 测试数据不存在
+        let mut problem = aplusb("standard");
+        problem.cases[1].input_file = "./tests/data/aplusb/missing.in".to_string();
+        let judgement = run("system_error_data", &problem, APLUSB);
+        assert_eq!(judgement.result, JudgeResult::SystemError);
+        assert_eq!(judgement.cases[1].result, JudgeResult::Accepted);
+        assert_eq!(judgement.cases[2].result, JudgeResult::SystemError);
+        assert!(judgement.cases[2].info.contains("missing.in"));
+    }
+
+    #[test]
+    fn interpreted_language() {
+        // 不编译，直接用解释器运行源代码（只使用 shell 内建命令，不需要创建子进程）
+        let shell = Language {
+            name: "Shell".to_string(),
+            file_name: "main.sh".to_string(),
+            command: Vec::new(),
+            run: Some(vec!["sh".to_string(), "%INPUT%".to_string()]),
+            compile_time_limit: 0,
+            time_multiplier: 2.0,
+            memory_multiplier: 2.0,
+        };
+        let judgement = judge(
+            &submit("read a; read b; echo $((a + b))"),
+            &aplusb("standard"),
+            &shell,
+            &Path::new("TMPDIR").join("judge-test-interpreted_language"),
+            &judge_config(),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert_eq!(judgement.cases[0].result, JudgeResult::CompilationSuccess);
+
+        // 解释器再次 exec 其他程序（如 pyenv 的启动脚本）时照常运行
+        let judgement = judge(
+            &submit("read a; read b; exec sh -c \"echo $((a + b))\""),
+            &aplusb("standard"),
+            &shell,
+            &Path::new("TMPDIR").join("judge-test-interpreted_language_exec"),
+            &judge_config(),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+    }
+
+    #[test]
+    fn canceled() {
+        // 第一个测试点运行时取消评测，程序被杀死，剩余测试点被跳过
+        let cancel = AtomicBool::new(false);
+        let started = AtomicBool::new(false);
+        let now = std::time::Instant::now();
+        let judgement = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !started.load(Ordering::Relaxed) {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                cancel.store(true, Ordering::Relaxed);
+            });
+            judge(
+                &submit("fn main() { std::thread::sleep(std::time::Duration::from_secs(10)); }"),
+                &aplusb("standard"),
+                &rust(),
+                &Path::new("TMPDIR").join("judge-test-canceled"),
+                &judge_config(),
+                &cancel,
+                |judgement| {
+                    if judgement.cases[1].result == JudgeResult::Running {
+                        started.store(true, Ordering::Relaxed);
+                    }
+                },
+            )
+        });
+        assert!(now.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(judgement.result, JudgeResult::Skipped);
+        assert_eq!(judgement.cases[0].result, JudgeResult::CompilationSuccess);
+        assert!(judgement.cases[1..]
+            .iter()
+            .all(|x| x.result == JudgeResult::Skipped));
+    }
+
+    /// 仅当 `condition` 成立时输出正确答案的 A+B 程序
+    fn aplusb_if(condition: &str) -> String {
+        APLUSB.replace(
+            "println!(\"{}\", a + b);",
+            &format!(
+                "if {} {{ println!(\"{{}}\", a + b); }} else {{ println!(\"0\"); }}",
+                condition
+            ),
+        )
+    }
+
+    /// 必须使用沙箱的配置：命名空间或 seccomp 不可用时评测结果为 `System Error`
+    fn sandbox_required() -> config::Judge {
+        let mut config = judge_config();
+        config.sandbox.required = true;
+        config.sandbox.hidden_paths = vec!["Cargo.toml".to_string()];
+        config
+    }
+
+    /// 沙箱在当前系统上是否可用，不可用时跳过测试沙箱的用例
+    fn sandbox_available() -> bool {
+        if sandbox::namespaces_supported() && sandbox::seccomp_supported() {
+            return true;
+        }
+        eprintln!("namespaces or seccomp unavailable, skipping sandbox test");
+        false
+    }
+
+    #[test]
+    fn sandbox_filesystem() {
+        if !sandbox_available() {
+            return;
+        }
+        // 程序在当前任务目录中运行，其他任务的目录、需要隐藏的文件和题目数据不可读，文件系统只读
+        let secret = Path::new("TMPDIR").join("judge-test-secret");
+        fs::create_dir_all(&secret).unwrap();
+        fs::write(secret.join("main.rs"), "secret").unwrap();
+        let secret = fs::canonicalize(&secret).unwrap();
+        let cargo_toml = fs::canonicalize("Cargo.toml").unwrap();
+        let answer = fs::canonicalize("tests/data/aplusb/1.ans").unwrap();
+        let source_code = aplusb_if(&format!(
+            "std::env::current_dir().unwrap().ends_with(\"judge-test-sandbox_filesystem\") \
+             && std::fs::read(\"main.rs\").is_ok() \
+             && std::fs::read({:?}).is_err() \
+             && std::fs::read(\"../judge-test-secret/main.rs\").is_err() \
+             && std::fs::read({:?}).map_or(true, |x| x.is_empty()) \
+             && std::fs::read({:?}).is_err() \
+             && std::fs::write(\"sandbox.txt\", \"\").is_err()",
+            secret.join("main.rs"),
+            cargo_toml,
+            answer,
+        ));
+        let judgement = run_with_config(
+            "sandbox_filesystem",
+            &aplusb("standard"),
+            &source_code,
+            &sandbox_required(),
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        assert!(!Path::new("sandbox.txt").exists());
+
+        // 编译器同样无法读取其他任务的目录和需要隐藏的文件
+        let source_code = format!(
+            "const SECRET: &str = include_str!({:?}); fn main() {{ println!(\"{{}}\", SECRET); }}",
+            secret.join("main.rs"),
+        );
+        let judgement = run_with_config(
+            "sandbox_filesystem_compile",
+            &aplusb("standard"),
+            &source_code,
+            &sandbox_required(),
+        );
+        assert_eq!(judgement.result, JudgeResult::CompilationError);
+        let source_code = aplusb_if(&format!("include_str!({:?}).is_empty()", cargo_toml));
+        let judgement = run_with_config(
+            "sandbox_filesystem_compile",
+            &aplusb("standard"),
+            &source_code,
+            &sandbox_required(),
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+        fs::remove_dir_all(&secret).unwrap();
+    }
+
+    #[test]
+    fn sandbox_processes() {
+        if !sandbox_available() {
+            return;
+        }
+        // 不能创建子进程，可以创建线程，但线程数不能超过限制
+        let source_code = aplusb_if(
+            "std::process::Command::new(\"true\").status().is_err() \
+             && std::thread::spawn(|| 1).join().unwrap() == 1",
+        );
+        let judgement = run_with_config(
+            "sandbox_processes",
+            &aplusb("standard"),
+            &source_code,
+            &sandbox_required(),
+        );
+        assert_eq!(judgement.result, JudgeResult::Accepted);
+
+        let source_code = "fn main() { let threads: Vec<_> = (0..1000).map(|_| std::thread::spawn(|| std::thread::sleep(std::time::Duration::from_secs(1)))).collect(); println!(\"{}\", threads.len()); }";
+        let judgement = run_with_config(
+            "sandbox_threads",
+            &aplusb("standard"),
+            source_code,
+            &sandbox_required(),
+        );
+        assert_eq!(judgement.cases[1].result, JudgeResult::RuntimeError);
+
+        // 通过 x32 ABI 的系统调用号调用 fork 时直接结束进程
+        #[cfg(target_arch = "x86_64")]
+        {
+            let source_code = "fn main() { let ret: i64; unsafe { std::arch::asm!(\"syscall\", inlateout(\"rax\") 57i64 | 0x4000_0000 => ret, out(\"rcx\") _, out(\"r11\") _); } println!(\"{}\", ret); }";
+            let judgement = run_with_config(
+                "sandbox_x32",
+                &aplusb("standard"),
+                source_code,
+                &sandbox_required(),
+            );
+            assert_eq!(judgement.cases[1].result, JudgeResult::RuntimeError);
+            assert!(judgement.cases[1].info.contains("SIGSYS"));
+        }
+    }
+}
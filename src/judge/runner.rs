@@ -0,0 +1,297 @@
+//! 运行测试点程序并统计运行时间与内存峰值。
+//!
+//! - 内存峰值取常驻内存与私有可写内存中较大者，已申请但未写入的内存也计入，
+//!   以 `PROT_NONE` 预留的地址空间（如 malloc 的 arena、JVM 预留的堆）不计入；
+//!   程序被评测线程跟踪（`ptrace`），退出之前停下再统计一次，运行时间极短的程序也能得到内存峰值。
+//! - `time_limit` 限制 CPU 时间（用户态与内核态之和），超出时为 `Time Limit Exceeded`；
+//!   `wall_time_limit` 限制墙上时间（缺省为 CPU 时间限制的两倍再加一秒），
+//!   超出时 CPU 时间仍未超限说明程序在空闲等待，结果为 `Idleness Limit Exceeded`。
+//! - 程序以非零值退出或被信号终止时为 `Runtime Error`，返回值或信号名称记录在 `info` 中。
+//! - 测试点结果的 `time` 为 CPU 时间，`dynamic_ranking` 类型的题目为墙上时间，与排行榜一致。
+
+use std::fs;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref PTRACE_SUPPORTED: bool = probe_ptrace();
+}
+
+/// 检查子进程状态的时间间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// 运行一个测试点时的资源限制
+pub struct Limits {
+    /// CPU 时间（用户态与内核态之和）限制（微秒），0 表示不限制
+    pub time_limit: u64,
+    /// 墙上时间限制（微秒），0 表示不限制
+    pub wall_time_limit: u64,
+    /// 内存限制（字节），0 表示不限制。
+    /// 由 `run` 轮询检查，`sandbox::Policy::apply` 另外据此设置 `RLIMIT_AS` 作为硬上限
+    pub memory_limit: u64,
+    /// 程序写入的单个文件（包括重定向到文件的标准输出）大小限制（字节），0 表示不限制。
+    /// 由 `sandbox::Policy::apply` 设置的 `RLIMIT_FSIZE` 保证
+    pub output_limit: u64,
+    /// 线程数限制，0 表示不限制
+    pub max_threads: u64,
+}
+
+/// 子进程的运行结果
+pub struct RunResult {
+    /// 程序返回值，被信号终止（包括因超出限制被评测程序杀死）时为 `None`
+    pub code: Option<i32>,
+    /// 终止程序的信号，正常退出时为 `None`
+    pub signal: Option<i32>,
+    /// 是否因超出 CPU 时间限制而被杀死
+    pub time_limit_exceeded: bool,
+    /// 是否因超出墙上时间限制而被杀死
+    pub wall_time_limit_exceeded: bool,
+    pub memory_limit_exceeded: bool,
+    /// 是否因线程数超出限制而被杀死
+    pub thread_limit_exceeded: bool,
+    /// 是否因评测被取消而被杀死
+    pub canceled: bool,
+    /// 墙上时间（微秒）
+    pub wall_time: u64,
+    /// CPU 时间（微秒），即用户态与内核态时间之和
+    pub cpu_time: u64,
+    /// 内存峰值（字节），即常驻内存（RSS）峰值与私有可写内存峰值中较大的一个
+    pub memory: u64,
+}
+
+/// 启动 `command` 并等待其结束，期间统计内存使用并在超出限制或 `cancel` 被置位时杀死子进程。
+///
+/// 内存使用通过轮询 `/proc/<pid>/status` 得到：`VmHWM` 为常驻内存峰值，`VmData` 为私有可写内存，
+/// 后者包括已经申请但还没有写入的内存（如 `calloc` 得到的全零页面），两者任一超过内存限制即判定为内存超限。
+/// 这里不使用地址空间大小：malloc 的 arena 和 JVM 的堆等以 `PROT_NONE` 预留的地址空间不计入 `VmData`。
+/// 同时检查其中的 `Threads`，线程数超过限制时杀死子进程：沙箱设置的 `RLIMIT_NPROC` 对 root 不生效。
+///
+/// 运行过程中通过轮询 `/proc/<pid>/stat` 检查 CPU 时间是否超限（精度为一个时钟周期），
+/// 结束后报告的 CPU 时间取自 `wait4` 返回的 `ru_utime` 与 `ru_stime`。
+///
+/// 这里不使用 `wait4` 返回的 `ru_maxrss`：子进程由评测服务 fork 而来，
+/// `exec` 之前继承的服务进程内存也会计入其中。
+///
+/// 经 `stop_at_exec` 设置的子进程在 `exec` 之后停下，此后一直被跟踪到退出之前再停下一次，
+/// 每次停下时都采样内存；程序再次 `exec`（如通过脚本启动解释器）时同样停下并继续运行，
+/// 收到其他信号时转交信号并解除跟踪，之后只通过轮询统计内存。
+pub fn run(command: &mut Command, limits: &Limits, cancel: &AtomicBool) -> io::Result<RunResult> {
+    let now = Instant::now();
+    let child = command.spawn()?;
+    let pid = child.id() as libc::pid_t;
+
+    let mut memory = 0;
+    let mut time_limit_exceeded = false;
+    let mut wall_time_limit_exceeded = false;
+    let mut memory_limit_exceeded = false;
+    let mut thread_limit_exceeded = false;
+    let mut canceled = false;
+    let mut killed = false;
+    let mut exec_stopped = false;
+    let mut raw_status = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+
+    loop {
+        // 先采样再检查是否退出，尽量保证运行时间很短的程序也至少被采样一次
+        if let Some((used, threads)) = sample_status(pid) {
+            memory = memory.max(used);
+            if !killed && limits.memory_limit != 0 && used > limits.memory_limit {
+                memory_limit_exceeded = true;
+                killed = true;
+                kill(pid);
+            }
+            if !killed && limits.max_threads != 0 && threads > limits.max_threads {
+                thread_limit_exceeded = true;
+                killed = true;
+                kill(pid);
+            }
+        }
+
+        let ret = unsafe { libc::wait4(pid, &mut raw_status, libc::WNOHANG, &mut rusage) };
+        if ret == pid && libc::WIFSTOPPED(raw_status) {
+            if let Some((used, _)) = sample_status(pid) {
+                memory = memory.max(used);
+            }
+            let null = std::ptr::null_mut::<libc::c_void>();
+            let signal = libc::WSTOPSIG(raw_status);
+            let event = raw_status >> 16;
+            unsafe {
+                if !exec_stopped && signal == libc::SIGTRAP {
+                    exec_stopped = true;
+                    let options = libc::PTRACE_O_TRACEEXEC
+                        | libc::PTRACE_O_TRACEEXIT
+                        | libc::PTRACE_O_EXITKILL;
+                    libc::ptrace(
+                        libc::PTRACE_SETOPTIONS,
+                        pid,
+                        null,
+                        options as usize as *mut libc::c_void,
+                    );
+                    libc::ptrace(libc::PTRACE_CONT, pid, null, null);
+                } else if event == libc::PTRACE_EVENT_EXEC || event == libc::PTRACE_EVENT_EXIT {
+                    libc::ptrace(libc::PTRACE_CONT, pid, null, null);
+                } else {
+                    libc::ptrace(
+                        libc::PTRACE_DETACH,
+                        pid,
+                        null,
+                        signal as usize as *mut libc::c_void,
+                    );
+                }
+            }
+            continue;
+        } else if ret == pid {
+            break;
+        } else if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        if !killed && limits.time_limit != 0 {
+            if let Some(cpu_time) = sample_cpu_time(pid) {
+                if cpu_time > limits.time_limit {
+                    time_limit_exceeded = true;
+                    killed = true;
+                    kill(pid);
+                }
+            }
+        }
+        if !killed
+            && limits.wall_time_limit != 0
+            && now.elapsed() > Duration::from_micros(limits.wall_time_limit)
+        {
+            wall_time_limit_exceeded = true;
+            killed = true;
+            kill(pid);
+        }
+        if !killed && cancel.load(Ordering::Relaxed) {
+            canceled = true;
+            killed = true;
+            kill(pid);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let wall_time = now.elapsed().as_micros() as u64;
+    let cpu_time = timeval_micros(rusage.ru_utime) + timeval_micros(rusage.ru_stime);
+    let status = ExitStatus::from_raw(raw_status);
+    Ok(RunResult {
+        code: status.code(),
+        signal: status.signal(),
+        time_limit_exceeded,
+        wall_time_limit_exceeded,
+        memory_limit_exceeded,
+        thread_limit_exceeded,
+        canceled,
+        wall_time,
+        cpu_time,
+        memory,
+    })
+}
+
+/// 让 `command` 启动的子进程被评测线程跟踪（`PTRACE_TRACEME`），由 `run` 在其退出之前采样内存。
+/// 单核上子进程 `exec` 之后可能在评测线程被调度之前就已退出，这样运行时间极短的程序也能统计到内存使用。
+///
+/// 需要在沙箱安装禁止 `ptrace` 的 seccomp 过滤器之前调用，并且必须在调用 `run` 的线程中启动子进程。
+/// 当前系统不允许 `ptrace`（如 Yama 的 `ptrace_scope` 为 3）时不做任何事，只通过轮询统计内存。
+pub fn stop_at_exec(command: &mut Command) {
+    if *PTRACE_SUPPORTED {
+        trace_me(command);
+    }
+}
+
+fn trace_me(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            let null = std::ptr::null_mut::<libc::c_void>();
+            if libc::ptrace(libc::PTRACE_TRACEME, 0, null, null) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// 跟踪一个运行 `true` 的子进程，检查当前系统是否允许评测线程跟踪子进程
+fn probe_ptrace() -> bool {
+    let mut command = Command::new("true");
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    trace_me(&mut command);
+    let limits = Limits {
+        time_limit: 0,
+        wall_time_limit: 0,
+        memory_limit: 0,
+        output_limit: 0,
+        max_threads: 0,
+    };
+    match run(&mut command, &limits, &AtomicBool::new(false)) {
+        Ok(result) if result.code == Some(0) => true,
+        Ok(result) => {
+            log::warn!(
+                "runner: ptrace unavailable (code {:?}, signal {:?}), sampling memory by polling only",
+                result.code,
+                result.signal
+            );
+            false
+        }
+        Err(err) => {
+            log::warn!(
+                "runner: ptrace unavailable ({}), sampling memory by polling only",
+                err
+            );
+            false
+        }
+    }
+}
+
+/// 杀死尚未被回收的子进程，子进程是进程组组长时同时杀死整个进程组
+fn kill(pid: libc::pid_t) {
+    unsafe {
+        libc::kill(-pid, libc::SIGKILL);
+        libc::kill(pid, libc::SIGKILL);
+    }
+}
+
+/// 读取 `/proc/<pid>/status` 中 `VmHWM` 与 `VmData` 的较大者（字节）以及 `Threads`，进程已退出时返回 `None`
+fn sample_status(pid: libc::pid_t) -> Option<(u64, u64)> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let field = |name: &str| -> Option<u64> {
+        let line = status.lines().find(|x| x.starts_with(name))?;
+        line[name.len()..]
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()
+    };
+    let used = field("VmHWM:")?.max(field("VmData:")?);
+    Some((used * 1024, field("Threads:")?))
+}
+
+/// 读取 `/proc/<pid>/stat` 中的 `utime` 与 `stime` 之和（微秒），进程已退出时返回 `None`
+fn sample_cpu_time(pid: libc::pid_t) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // 第二个字段是括号括起来的程序名，其中可能含有空格，从最后一个右括号之后开始解析
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    // 右括号之后依次为第 3 个字段 state 到第 13 个字段 majflt，随后是 utime 与 stime
+    let utime: u64 = fields.nth(11)?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+    Some((utime + stime) * 1_000_000 / ticks)
+}
+
+fn timeval_micros(time: libc::timeval) -> u64 {
+    time.tv_sec as u64 * 1_000_000 + time.tv_usec as u64
+}
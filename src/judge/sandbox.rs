@@ -0,0 +1,703 @@
+//! 运行提交的程序和编译器时使用的沙箱。
+//!
+//! 在子进程 `exec` 之前依次完成：
+//!
+//! 1. 进入新的用户、挂载、网络、IPC 与 UTS 命名空间，使程序无法访问网络；
+//! 2. 将所有挂载点重新挂载为只读，并用空的 tmpfs 覆盖评测临时目录与需要隐藏的目录、
+//!    用 `/dev/null` 覆盖需要隐藏的文件（配置文件、持久化数据文件、题目数据等），
+//!    只把当前任务的目录重新挂载回来并在其中运行，使程序无法读取其他提交的代码和答案文件；
+//! 3. 设置 CPU 时间、地址空间、文件大小、打开文件数、线程数等资源限制；
+//! 4. 安装 seccomp 过滤器，禁止创建进程、调试其他进程、向其他进程发送信号等系统调用。
+//!
+//! 编译器只完成前两步，并且当前任务的目录可写。
+//!
+//! 命名空间和 seccomp 在当前系统上不可用时（例如容器中禁止创建用户命名空间）默认拒绝评测；
+//! 关闭 `required` 时则记录警告并跳过。
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::ptr;
+
+use lazy_static::lazy_static;
+
+use super::runner::Limits;
+use crate::config::{self, Problem};
+
+lazy_static! {
+    static ref NAMESPACES_SUPPORTED: bool = probe("namespaces", || {
+        let uid_map = CString::new(format!("0 {} 1", unsafe { libc::getuid() })).unwrap();
+        let gid_map = CString::new(format!("0 {} 1", unsafe { libc::getgid() })).unwrap();
+        move || unsafe {
+            enter_namespaces(&uid_map, &gid_map)?;
+            remount_read_only(&CString::new("/").unwrap(), 0)
+        }
+    });
+    static ref SECCOMP_SUPPORTED: bool = probe("seccomp", || || unsafe { install_seccomp() });
+}
+
+/// 当前系统是否支持在命名空间中运行程序
+pub fn namespaces_supported() -> bool {
+    *NAMESPACES_SUPPORTED
+}
+
+/// 当前系统是否支持 seccomp 过滤器
+pub fn seccomp_supported() -> bool {
+    *SECCOMP_SUPPORTED
+}
+
+/// 一次评测中编译和所有测试点共用的沙箱设置
+pub struct Policy {
+    config: config::Sandbox,
+    /// 当前任务的目录（绝对路径）
+    work_dir: PathBuf,
+    /// 需要隐藏的文件或目录（绝对路径），不含评测临时目录中的内容
+    hidden_paths: Vec<PathBuf>,
+}
+
+impl Policy {
+    /// 为在 `work_dir` 中评测 `problem` 创建沙箱设置，`work_dir` 必须已经存在。
+    ///
+    /// 需要隐藏的路径为配置中的 `hidden_paths` 与当前题目的数据文件及其所在目录，不存在的路径会被忽略。
+    /// 配置了 `required` 而命名空间或 seccomp 不可用时返回错误。
+    pub fn new(config: &config::Sandbox, problem: &Problem, work_dir: &Path) -> io::Result<Self> {
+        if config.enabled && config.required {
+            for (name, supported) in [
+                ("namespaces", namespaces_supported()),
+                ("seccomp", seccomp_supported()),
+            ] {
+                if !supported {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("{} unavailable but required", name),
+                    ));
+                }
+            }
+        }
+
+        let work_dir = fs::canonicalize(work_dir)?;
+        let tmp_root = work_dir.parent().unwrap_or(&work_dir).to_path_buf();
+        let data_files = problem
+            .cases
+            .iter()
+            .flat_map(|x| [&x.input_file, &x.answer_file])
+            .map(Path::new);
+        let mut paths: Vec<PathBuf> = config
+            .hidden_paths
+            .iter()
+            .map(Path::new)
+            .chain(data_files.clone())
+            .chain(data_files.filter_map(Path::parent))
+            .filter_map(|x| fs::canonicalize(x).ok())
+            // 不能隐藏当前任务目录的上级目录，评测临时目录中的内容已经被整体隐藏
+            .filter(|x| !work_dir.starts_with(x) && !x.starts_with(&tmp_root))
+            .collect();
+        // 排序后上级目录在前，上级目录已经被隐藏的路径不必（也无法）再隐藏
+        paths.sort();
+        let mut hidden_paths: Vec<PathBuf> = Vec::new();
+        for path in paths {
+            if !hidden_paths.iter().any(|x| path.starts_with(x)) {
+                hidden_paths.push(path);
+            }
+        }
+        Ok(Policy {
+            config: config.clone(),
+            work_dir,
+            hidden_paths,
+        })
+    }
+
+    /// 程序的线程数上限，0 表示不限制
+    pub fn max_threads(&self) -> u64 {
+        if self.config.enabled {
+            self.config.max_processes
+        } else {
+            0
+        }
+    }
+
+    /// 让编译器 `command` 在沙箱中运行：文件系统与运行程序时相同，但当前任务的目录可写，
+    /// 编译器的临时文件也写在其中。编译器会启动链接器等其他进程，不限制进程数和系统调用。
+    ///
+    /// 编译器单独成为一个进程组，以便超时时和它启动的进程一起被杀死。
+    pub fn apply_compiler(&self, command: &mut Command) -> io::Result<()> {
+        command.current_dir(&self.work_dir);
+        let filesystem = if self.config.enabled && namespaces_supported() {
+            command.env("TMPDIR", &self.work_dir);
+            Some(Filesystem::new(&self.work_dir, &self.hidden_paths, true)?)
+        } else {
+            None
+        };
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(filesystem) = &filesystem {
+                    filesystem.enter()?;
+                }
+                check(libc::setpgid(0, 0))
+            });
+        }
+        Ok(())
+    }
+
+    /// 让 `command` 在沙箱中运行，`limits` 用于设置 CPU 时间、地址空间和输出大小限制。
+    ///
+    /// 程序总是在当前任务的目录中运行，关闭沙箱时仍然会限制输出大小和地址空间。
+    pub fn apply(&self, command: &mut Command, limits: &Limits) -> io::Result<()> {
+        command.current_dir(&self.work_dir);
+        if !self.config.enabled {
+            let mut rlimits = Vec::new();
+            if limits.output_limit != 0 {
+                rlimits.push((libc::RLIMIT_FSIZE, limits.output_limit));
+            }
+            if limits.memory_limit != 0 {
+                rlimits.push((libc::RLIMIT_AS, address_space_limit(limits.memory_limit)));
+            }
+            unsafe {
+                command.pre_exec(move || {
+                    for &(resource, limit) in rlimits.iter() {
+                        set_rlimit(resource, limit)?;
+                    }
+                    Ok(())
+                });
+            }
+            return Ok(());
+        }
+
+        let filesystem = if namespaces_supported() {
+            Some(Filesystem::new(&self.work_dir, &self.hidden_paths, false)?)
+        } else {
+            None
+        };
+
+        // 文件系统只读时程序只能写入标准输出，文件大小限制就是题目的输出大小限制；
+        // 否则程序还能写入其他文件，取两者中较大的一个，标准输出是否超出限制由评测程序在运行结束后检查
+        let file_size_limit = if limits.output_limit == 0 {
+            self.config.max_file_size
+        } else if filesystem.is_some() {
+            limits.output_limit
+        } else {
+            self.config.max_file_size.max(limits.output_limit)
+        };
+        // 子进程在 fork 之后、exec 之前不能安全地分配内存，所需的数据都提前准备好
+        let mut rlimits = vec![
+            (libc::RLIMIT_CORE, 0),
+            (libc::RLIMIT_FSIZE, file_size_limit),
+            (libc::RLIMIT_NOFILE, self.config.max_open_files),
+        ];
+        if limits.time_limit != 0 {
+            // CPU 时间限制比时间限制至少多一秒，超时一般由评测程序计时发现
+            rlimits.push((libc::RLIMIT_CPU, limits.time_limit / 1_000_000 + 2));
+        }
+        if limits.memory_limit != 0 {
+            rlimits.push((libc::RLIMIT_AS, address_space_limit(limits.memory_limit)));
+        }
+        // 在新的用户命名空间中，`RLIMIT_NPROC` 只计算命名空间中的进程和线程；
+        // 否则会把评测服务所在用户的所有进程都计算在内。评测服务以 root 运行时该限制不生效，
+        // 由 `runner` 检查线程数
+        if filesystem.is_some() && self.config.max_processes != 0 {
+            rlimits.push((libc::RLIMIT_NPROC, self.config.max_processes));
+        }
+        let seccomp = seccomp_supported();
+
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(filesystem) = &filesystem {
+                    filesystem.enter()?;
+                }
+                // 程序单独成为一个进程组，`kill(0, ...)` 不会影响评测服务
+                check(libc::setpgid(0, 0))?;
+                for &(resource, limit) in rlimits.iter() {
+                    set_rlimit(resource, limit)?;
+                }
+                if seccomp {
+                    install_seccomp()?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 进入命名空间后需要完成的挂载操作
+struct Filesystem {
+    uid_map: CString,
+    gid_map: CString,
+    /// 挂载点及其需要保留的挂载选项
+    mounts: Vec<(CString, libc::c_ulong)>,
+    work_dir: CString,
+    /// 当前任务的目录是否可写
+    writable: bool,
+    /// 评测临时目录，即当前任务目录的上级目录
+    tmp_root: CString,
+    /// 需要隐藏的路径及其是否为目录
+    hidden_paths: Vec<(CString, bool)>,
+}
+
+impl Filesystem {
+    fn new(work_dir: &Path, hidden_paths: &[PathBuf], writable: bool) -> io::Result<Self> {
+        let tmp_root = work_dir.parent().unwrap_or(work_dir);
+        Ok(Filesystem {
+            uid_map: CString::new(format!("0 {} 1", unsafe { libc::getuid() }))?,
+            gid_map: CString::new(format!("0 {} 1", unsafe { libc::getgid() }))?,
+            mounts: mount_points()?,
+            work_dir: path_to_cstring(work_dir)?,
+            writable,
+            tmp_root: path_to_cstring(tmp_root)?,
+            hidden_paths: hidden_paths
+                .iter()
+                .map(|x| Ok((path_to_cstring(x)?, x.is_dir())))
+                .collect::<io::Result<_>>()?,
+        })
+    }
+
+    /// 在子进程中调用：进入新的命名空间并完成挂载
+    unsafe fn enter(&self) -> io::Result<()> {
+        enter_namespaces(&self.uid_map, &self.gid_map)?;
+
+        if self.writable {
+            // 把当前任务目录绑定挂载到自身，成为单独的挂载点，不随下面所在的文件系统一起变为只读
+            check(libc::mount(
+                self.work_dir.as_ptr(),
+                self.work_dir.as_ptr(),
+                ptr::null(),
+                libc::MS_BIND,
+                ptr::null(),
+            ))?;
+        }
+
+        // 先打开当前任务目录，临时目录被覆盖后再把它挂载回原来的位置
+        let work_dir = libc::open(
+            self.work_dir.as_ptr(),
+            libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        );
+        check(work_dir)?;
+
+        // 所有挂载点只读，根目录必须成功，其余挂载点（如 /proc 下的特殊挂载）尽力而为
+        for (mount_point, flags) in self.mounts.iter() {
+            let ret = remount_read_only(mount_point, *flags);
+            if mount_point.as_bytes() == b"/" {
+                ret?;
+            }
+        }
+
+        // 用空的 tmpfs 覆盖临时目录，只挂载回当前任务目录
+        check(libc::mount(
+            cstr(b"tmpfs\0"),
+            self.tmp_root.as_ptr(),
+            cstr(b"tmpfs\0"),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            cstr(b"size=64k,mode=755\0") as *const libc::c_void,
+        ))?;
+        check(libc::mkdir(self.work_dir.as_ptr(), 0o755))?;
+        let mut source = [0u8; 64];
+        fd_path(work_dir, &mut source);
+        check(libc::mount(
+            source.as_ptr() as *const libc::c_char,
+            self.work_dir.as_ptr(),
+            ptr::null(),
+            libc::MS_BIND,
+            ptr::null(),
+        ))?;
+        libc::close(work_dir);
+        if !self.writable {
+            remount_read_only(&self.work_dir, 0)?;
+        }
+        check(libc::mount(
+            ptr::null(),
+            self.tmp_root.as_ptr(),
+            ptr::null(),
+            libc::MS_REMOUNT | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+            ptr::null(),
+        ))?;
+
+        // 隐藏配置文件、持久化数据和题目数据等
+        for (path, is_dir) in self.hidden_paths.iter() {
+            if *is_dir {
+                check(libc::mount(
+                    cstr(b"tmpfs\0"),
+                    path.as_ptr(),
+                    cstr(b"tmpfs\0"),
+                    libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                    cstr(b"size=4k\0") as *const libc::c_void,
+                ))?;
+            } else {
+                check(libc::mount(
+                    cstr(b"/dev/null\0"),
+                    path.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND,
+                    ptr::null(),
+                ))?;
+            }
+        }
+
+        // 进入命名空间之前切换到的工作目录仍是原来的目录，需要重新切换到挂载回来的当前任务目录
+        check(libc::chdir(self.work_dir.as_ptr()))
+    }
+}
+
+/// 进入新的用户、挂载、网络、IPC 与 UTS 命名空间，并把当前用户映射为命名空间中的 root
+unsafe fn enter_namespaces(uid_map: &CString, gid_map: &CString) -> io::Result<()> {
+    check(libc::unshare(
+        libc::CLONE_NEWUSER
+            | libc::CLONE_NEWNS
+            | libc::CLONE_NEWNET
+            | libc::CLONE_NEWIPC
+            | libc::CLONE_NEWUTS,
+    ))?;
+    write_file(b"/proc/self/setgroups\0", b"deny")?;
+    write_file(b"/proc/self/uid_map\0", uid_map.as_bytes())?;
+    write_file(b"/proc/self/gid_map\0", gid_map.as_bytes())?;
+    // 挂载操作不传播回评测服务所在的命名空间
+    check(libc::mount(
+        ptr::null(),
+        cstr(b"/\0"),
+        ptr::null(),
+        libc::MS_REC | libc::MS_PRIVATE,
+        ptr::null(),
+    ))
+}
+
+/// 将挂载点重新挂载为只读，`flags` 为需要保留的 nosuid、nodev 等选项
+unsafe fn remount_read_only(mount_point: &CString, flags: libc::c_ulong) -> io::Result<()> {
+    check(libc::mount(
+        ptr::null(),
+        mount_point.as_ptr(),
+        ptr::null(),
+        libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | flags,
+        ptr::null(),
+    ))
+}
+
+/// 写入文件，`path` 以 0 结尾
+unsafe fn write_file(path: &[u8], content: &[u8]) -> io::Result<()> {
+    let fd = libc::open(cstr(path), libc::O_WRONLY | libc::O_CLOEXEC);
+    check(fd)?;
+    let ret = libc::write(fd, content.as_ptr() as *const libc::c_void, content.len());
+    libc::close(fd);
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 不分配内存地将 `/proc/self/fd/<fd>` 写入 `buf`（以 0 结尾）
+fn fd_path(fd: libc::c_int, buf: &mut [u8; 64]) {
+    let prefix = b"/proc/self/fd/";
+    buf[..prefix.len()].copy_from_slice(prefix);
+    let mut digits = [0u8; 12];
+    let mut n = fd as u32;
+    let mut len = 0;
+    loop {
+        digits[len] = b'0' + (n % 10) as u8;
+        len += 1;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    for i in 0..len {
+        buf[prefix.len() + i] = digits[len - 1 - i];
+    }
+    buf[prefix.len() + len] = 0;
+}
+
+/// 读取 `/proc/self/mountinfo`，返回所有挂载点及其需要在重新挂载时保留的选项
+fn mount_points() -> io::Result<Vec<(CString, libc::c_ulong)>> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    let mut mounts = Vec::new();
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() < 6 {
+            continue;
+        }
+        let mut flags = 0;
+        for option in fields[5].split(',') {
+            flags |= match option {
+                "nosuid" => libc::MS_NOSUID,
+                "nodev" => libc::MS_NODEV,
+                "noexec" => libc::MS_NOEXEC,
+                "noatime" => libc::MS_NOATIME,
+                "nodiratime" => libc::MS_NODIRATIME,
+                "relatime" => libc::MS_RELATIME,
+                _ => 0,
+            };
+        }
+        mounts.push((CString::new(unescape(fields[4]))?, flags));
+    }
+    Ok(mounts)
+}
+
+/// 还原 mountinfo 中以 `\ooo` 转义的空格等字符
+fn unescape(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && bytes[i + 1..i + 4].iter().all(u8::is_ascii_digit)
+        {
+            let value =
+                (bytes[i + 1] - b'0') * 64 + (bytes[i + 2] - b'0') * 8 + (bytes[i + 3] - b'0');
+            result.push(value);
+            i += 4;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+/// 地址空间的硬上限是内存限制的这么多倍，且不低于 [`MIN_ADDRESS_SPACE`]
+const ADDRESS_SPACE_FACTOR: u64 = 16;
+/// 地址空间硬上限的最小值，JVM 启动时即使堆很小也会预留约 1GB 的地址空间
+const MIN_ADDRESS_SPACE: u64 = 2 << 30;
+
+/// 内存限制为 `memory_limit` 时的 `RLIMIT_AS`。
+///
+/// 内存限制由 `runner` 轮询检查，一次申请并写入大量内存的程序可能在两次检查之间耗尽主机内存，
+/// 地址空间的限制由内核保证，作为最后的防线；预留而未使用的地址空间也计入其中，所以留出足够的余量
+fn address_space_limit(memory_limit: u64) -> u64 {
+    memory_limit
+        .saturating_mul(ADDRESS_SPACE_FACTOR)
+        .max(MIN_ADDRESS_SPACE)
+}
+
+/// 以 0 结尾的字节串转换为 C 字符串指针
+fn cstr(bytes: &[u8]) -> *const libc::c_char {
+    debug_assert_eq!(bytes.last(), Some(&0));
+    bytes.as_ptr() as *const libc::c_char
+}
+
+fn path_to_cstring(path: &Path) -> io::Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// 将资源限制的软限制和硬限制都设为 `limit`
+fn set_rlimit(resource: libc::__rlimit_resource_t, limit: u64) -> io::Result<()> {
+    let rlimit = libc::rlimit {
+        rlim_cur: limit,
+        rlim_max: limit,
+    };
+    check(unsafe { libc::setrlimit(resource, &rlimit) })
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// 在子进程中执行 `setup` 并运行 `true`，检查当前系统是否支持某项沙箱功能
+fn probe<F, G>(name: &str, setup: G) -> bool
+where
+    F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+    G: FnOnce() -> F,
+{
+    let mut command = Command::new("true");
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    unsafe {
+        command.pre_exec(setup());
+    }
+    match command.status() {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            log::warn!("sandbox: {} unavailable ({})", name, status);
+            false
+        }
+        Err(err) => {
+            log::warn!("sandbox: {} unavailable ({})", name, err);
+            false
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// 被禁止的系统调用，调用时返回 `EPERM`
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_fork,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_vfork,
+    libc::SYS_tkill,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_pidfd_open,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_socket,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    // io_uring 中的操作不经过 seccomp 检查
+    libc::SYS_io_uring_setup,
+];
+
+/// seccomp 过滤器的最大长度
+const MAX_FILTER_LEN: usize = 128;
+
+/// 在栈上构造的 BPF 程序，子进程中构造时不需要分配内存
+struct Filter {
+    code: [libc::sock_filter; MAX_FILTER_LEN],
+    len: usize,
+}
+
+impl Filter {
+    fn push(&mut self, code: u32, jt: u8, jf: u8, k: u32) {
+        self.code[self.len] = libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        };
+        self.len += 1;
+    }
+
+    /// 读取 `seccomp_data` 中偏移为 `offset` 的 32 位数据
+    fn load(&mut self, offset: u32) {
+        self.push(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0, 0, offset);
+    }
+
+    fn jump_if_equal(&mut self, k: u32, jt: u8, jf: u8) {
+        self.push(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, jt, jf, k);
+    }
+
+    fn ret(&mut self, k: u32) {
+        self.push(libc::BPF_RET | libc::BPF_K, 0, 0, k);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// `seccomp_data` 中各字段的偏移
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+/// 第一个参数的低 32 位（小端序）
+const OFFSET_ARG0: u32 = 16;
+
+/// 安装 seccomp 过滤器：
+///
+/// - 禁止 `fork`、`vfork` 与不带 `CLONE_THREAD` 的 `clone`，程序只能创建线程；
+/// - `kill` 与 `tgkill` 只能向自己（或自己的进程组）发送信号；
+/// - 禁止 [`DENIED_SYSCALLS`] 中的系统调用；
+/// - 其他架构（包括 x32 ABI）的系统调用直接结束进程。
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+unsafe fn install_seccomp() -> io::Result<()> {
+    let allow = libc::SECCOMP_RET_ALLOW;
+    let eperm = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+    let pid = libc::getpid() as u32;
+
+    let mut filter = Filter {
+        code: [libc::sock_filter {
+            code: 0,
+            jt: 0,
+            jf: 0,
+            k: 0,
+        }; MAX_FILTER_LEN],
+        len: 0,
+    };
+    // 其他架构的系统调用号不同，直接结束进程
+    filter.load(OFFSET_ARCH);
+    filter.jump_if_equal(AUDIT_ARCH, 1, 0);
+    filter.ret(libc::SECCOMP_RET_KILL_PROCESS);
+    filter.load(OFFSET_NR);
+    // x32 ABI 的系统调用与 x86-64 的架构相同，调用号带有 `__X32_SYSCALL_BIT`，同样直接结束进程
+    #[cfg(target_arch = "x86_64")]
+    {
+        filter.push(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            0,
+            1,
+            X32_SYSCALL_BIT,
+        );
+        filter.ret(libc::SECCOMP_RET_KILL_PROCESS);
+    }
+
+    // clone：只允许创建线程
+    filter.jump_if_equal(libc::SYS_clone as u32, 0, 4);
+    filter.load(OFFSET_ARG0);
+    filter.push(
+        libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+        0,
+        1,
+        libc::CLONE_THREAD as u32,
+    );
+    filter.ret(allow);
+    filter.ret(eperm);
+
+    // clone3 的参数在内存中无法检查，返回 ENOSYS 使 glibc 回退到 clone
+    filter.jump_if_equal(libc::SYS_clone3 as u32, 0, 1);
+    filter.ret(libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32);
+
+    // kill：只允许发给自己或自己的进程组
+    filter.jump_if_equal(libc::SYS_kill as u32, 0, 5);
+    filter.load(OFFSET_ARG0);
+    filter.jump_if_equal(pid, 2, 0);
+    filter.jump_if_equal(0, 1, 0);
+    filter.ret(eperm);
+    filter.ret(allow);
+
+    // tgkill：只允许发给自己的线程
+    filter.jump_if_equal(libc::SYS_tgkill as u32, 0, 3);
+    filter.load(OFFSET_ARG0);
+    filter.jump_if_equal(pid, 1, 0);
+    filter.ret(eperm);
+    filter.ret(allow);
+
+    for &syscall in DENIED_SYSCALLS {
+        filter.jump_if_equal(syscall as u32, 0, 1);
+        filter.ret(eperm);
+    }
+    filter.ret(allow);
+
+    let program = libc::sock_fprog {
+        len: filter.len as u16,
+        filter: filter.code.as_mut_ptr(),
+    };
+    check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+    check(libc::prctl(
+        libc::PR_SET_SECCOMP,
+        libc::SECCOMP_MODE_FILTER,
+        &program as *const libc::sock_fprog,
+    ))
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+unsafe fn install_seccomp() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "seccomp filter is not implemented for this architecture",
+    ))
+}
@@ -0,0 +1,93 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicBool;
+
+use super::runner::{self, Limits};
+use super::Verdict;
+use crate::api::jobs::JudgeResult;
+
+/// special judge 的运行时间限制（微秒），同时用作 CPU 时间和墙上时间限制
+const SPJ_TIME_LIMIT: u64 = 10_000_000;
+
+/// 运行 special judge 检查程序输出，返回测试点结果、得分比例与附加信息。
+///
+/// 命令中的 `%INPUT%`、`%OUTPUT%`、`%ANSWER%` 分别替换为测试点输入文件、程序输出文件与答案文件。
+/// special judge 输出的第一行为测试点结果（`Accepted` 或 `Wrong Answer`），
+/// 或者 0 到 1 之间的得分比例（如 `0.5`），第二行为附加信息；
+/// 若 special judge 运行失败或输出无法识别，结果为 `SPJ Error`。
+pub fn special_judge(
+    command: &[String],
+    input_file: &str,
+    output_file: &Path,
+    answer_file: &str,
+    work_dir: &Path,
+    cancel: &AtomicBool,
+) -> Verdict {
+    if command.is_empty() {
+        return spj_error("special judge is not configured".to_string());
+    }
+
+    let commands: Vec<String> = command
+        .iter()
+        .map(|x| match x.as_str() {
+            "%INPUT%" => input_file.to_string(),
+            "%OUTPUT%" => output_file.to_string_lossy().into_owned(),
+            "%ANSWER%" => answer_file.to_string(),
+            _ => x.to_string(),
+        })
+        .collect();
+
+    let spj_output = work_dir.join("spj.out");
+    let out_file = match File::create(&spj_output) {
+        Ok(file) => file,
+        Err(err) => return spj_error(format!("cannot create {}: {}", spj_output.display(), err)),
+    };
+    let mut child = Command::new(&commands[0]);
+    child
+        .args(&commands[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(out_file))
+        .stderr(Stdio::null());
+    let limits = Limits {
+        time_limit: SPJ_TIME_LIMIT,
+        wall_time_limit: SPJ_TIME_LIMIT,
+        memory_limit: 0,
+        output_limit: 0,
+        max_threads: 0,
+    };
+    let run_result = match runner::run(&mut child, &limits, cancel) {
+        Ok(run_result) => run_result,
+        Err(err) => return spj_error(format!("cannot run special judge: {}", err)),
+    };
+    if run_result.canceled {
+        return spj_error("special judge was canceled".to_string());
+    }
+    if run_result.time_limit_exceeded || run_result.wall_time_limit_exceeded {
+        return spj_error("special judge timed out".to_string());
+    }
+    match run_result.code {
+        Some(0) => {}
+        Some(code) => return spj_error(format!("special judge exited with code {}", code)),
+        None => return spj_error("special judge was killed by a signal".to_string()),
+    }
+
+    // 第一行：测试点结果，第二行：附加信息
+    let content = fs::read_to_string(&spj_output).unwrap_or_default();
+    let mut lines = content.lines();
+    let first_line = lines.next().unwrap_or("").trim();
+    let info = lines.next().unwrap_or("").trim().to_string();
+    match serde_json::from_value(serde_json::Value::String(first_line.to_string())) {
+        Ok(JudgeResult::Accepted) => return Verdict::new(JudgeResult::Accepted, info),
+        Ok(JudgeResult::WrongAnswer) => return Verdict::new(JudgeResult::WrongAnswer, info),
+        _ => {}
+    }
+    match first_line.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Verdict::partial(ratio, info),
+        _ => spj_error(format!("invalid special judge result: {:?}", first_line)),
+    }
+}
+
+fn spj_error(info: String) -> Verdict {
+    Verdict::new(JudgeResult::SPJError, info)
+}
@@ -1,24 +1,30 @@
 use actix_web::{middleware::Logger, web, App, HttpServer};
-use env_logger;
 use lazy_static::lazy_static;
-use log;
 
-use serde_json;
 use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 mod api;
 mod args;
 mod config;
+mod judge;
+mod queue;
+mod store;
 
 use api::contests::{get_contests, get_contests_by_id, get_ranklist, post_contests, Contest};
 use api::hello::{exit, greet};
-use api::jobs::{get_jobs, get_jobs_by_id, post_jobs, put_jobs_by_id, JobCounter, JobResponse};
+use api::jobs::{
+    delete_compile_cache, delete_jobs_by_id, get_jobs, get_jobs_by_id, post_jobs, put_jobs_by_id,
+    JobCounter, JobResponse, State,
+};
 use api::users::{get_users, post_users, User};
 use args::Args;
 use args::Parser;
 use config::Config;
+use queue::JobQueue;
 
 // 全局变量
 
@@ -35,40 +41,107 @@ lazy_static! {
 async fn main() -> std::io::Result<()> {
     // 初始化Logger
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    log::info!("starting HTTP server at http://localhost:12345");
 
     // 读取config
     let args = Args::parse();
     let config_path = Path::new(&args.config);
     let config_str = fs::read_to_string(config_path)?;
-    let config: Config = serde_json::from_str(&config_str)?;
+    let mut config: Config = serde_json::from_str(&config_str)?;
 
-    // 创建job counter
+    // 提交的程序和编译器不能读取配置文件、持久化数据（其中有所有用户的代码）、题目数据与编译缓存
+    let hidden_paths = &mut config.judge.sandbox.hidden_paths;
+    hidden_paths.push(args.config.clone());
+    hidden_paths.push(args.data_file.clone());
+    hidden_paths.push(config.judge.compile_cache.dir.clone());
+    for case in config.problems.iter().flat_map(|x| x.cases.iter()) {
+        for file in [&case.input_file, &case.answer_file] {
+            hidden_paths.push(file.clone());
+            if let Some(dir) = Path::new(file).parent() {
+                hidden_paths.push(dir.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    // 恢复持久化数据
+    store::open(Path::new(&args.data_file), args.flush_data)?;
+
+    // 创建job counter，从已有的最大测评id继续
+    let max_job_id = JOB_LIST.lock().unwrap().iter().map(|x| x.id).max();
     let counter = web::Data::new(JobCounter {
-        counter: Mutex::new(-1),
+        counter: Mutex::new(max_job_id.unwrap_or(-1)),
     });
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(config.clone()))
-            .app_data(counter.clone())
-            .wrap(Logger::default())
-            .route("/hello", web::get().to(|| async { "Hello World!" }))
-            .service(greet)
-            // DO NOT REMOVE: used in automatic testing
-            .service(exit)
-            .service(post_jobs)
-            .service(get_jobs)
-            .service(get_jobs_by_id)
-            .service(put_jobs_by_id)
-            .service(get_users)
-            .service(post_users)
-            .service(get_ranklist)
-            .service(post_contests)
-            .service(get_contests)
-            .service(get_contests_by_id)
-    })
-    .bind(("127.0.0.1", 12345))?
-    .run()
-    .await
+    // 启动后台评测线程池，重新评测上次退出时尚未完成的任务
+    let queue = web::Data::new(JobQueue::start(&config));
+    for job in JOB_LIST
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .filter(|x| x.state == State::Queueing || x.state == State::Running)
+    {
+        job.state = State::Queueing;
+        store::save_job(job);
+        queue.push(job.id).expect("judge workers have exited");
+    }
+
+    let bind = config.server.clone();
+    let mut server =
+        HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(counter.clone())
+                .app_data(queue.clone())
+                // 路径参数无法解析时（如 /contests/abc/ranklist）同样返回 ERR_NOT_FOUND
+                .app_data(web::PathConfig::default().error_handler(|err, _| {
+                    api::error::ApiError::not_found(err.to_string()).into()
+                }))
+                .wrap_fn(api::error::catch_panic)
+                .wrap(Logger::default())
+                .route("/hello", web::get().to(|| async { "Hello World!" }))
+                .service(greet)
+                // DO NOT REMOVE: used in automatic testing
+                .service(exit)
+                .service(post_jobs)
+                .service(get_jobs)
+                .service(get_jobs_by_id)
+                .service(put_jobs_by_id)
+                .service(delete_jobs_by_id)
+                .service(delete_compile_cache)
+                .service(get_users)
+                .service(post_users)
+                .service(get_ranklist)
+                .service(post_contests)
+                .service(get_contests)
+                .service(get_contests_by_id)
+        });
+
+    // 监听配置中指定的地址
+    log::info!(
+        "starting HTTP server at http://{}:{}",
+        bind.bind_address,
+        bind.bind_port
+    );
+    server = server.bind((bind.bind_address.as_str(), bind.bind_port))?;
+    for address in bind.additional_addresses.iter() {
+        log::info!("starting HTTP server at http://{}", address);
+        server = server.bind(address)?;
+    }
+    if let Some(path) = &bind.unix_socket {
+        // 删除上次运行遗留的 socket 文件，路径上已有其他文件时不删除，直接报错
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists and is not a socket", path),
+                ))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        log::info!("starting HTTP server at unix:{}", path);
+        server = server.bind_uds(path)?;
+    }
+
+    server.run().await
 }
@@ -0,0 +1,59 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SendError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::api::jobs::{fail_job, judge_job};
+use crate::config::Config;
+
+/// 评测队列：HTTP handler 只负责把 job id 放入队列，
+/// 由后台的评测线程池依次取出并完成评测
+pub struct JobQueue {
+    sender: Mutex<Sender<i32>>,
+}
+
+impl JobQueue {
+    /// 按照配置启动评测线程池
+    pub fn start(config: &Config) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = config.judge.workers.max(1);
+        for i in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let config = config.clone();
+            thread::Builder::new()
+                .name(format!("judge-worker-{}", i))
+                .spawn(move || worker(receiver, config))
+                .expect("failed to spawn judge worker");
+        }
+        log::info!("started {} judge workers", workers);
+
+        JobQueue {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// 将测评任务加入队列，所有评测线程都已退出时返回错误
+    pub fn push(&self, id: i32) -> Result<(), SendError<i32>> {
+        self.sender.lock().unwrap().send(id)
+    }
+}
+
+/// 评测线程：不断从队列中取出 job id 并评测
+fn worker(receiver: Arc<Mutex<Receiver<i32>>>, config: Config) {
+    loop {
+        // 取出任务后立即释放锁，使其他评测线程可以继续取任务
+        let id = match receiver.lock().unwrap().recv() {
+            Ok(id) => id,
+            Err(_) => break,
+        };
+
+        log::info!("judging job {}", id);
+        // 某个任务评测时 panic 不应让评测线程退出，该任务结果为 System Error
+        if panic::catch_unwind(AssertUnwindSafe(|| judge_job(id, &config))).is_err() {
+            log::error!("judge worker panicked while judging job {}", id);
+            fail_job(id, "judge worker panicked".to_string());
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! 持久化存储：用户和比赛的每次修改以及评测任务的每次状态变化都以一行 JSON 追加写入数据文件，
+//! 启动时重放数据文件恢复 USER_LIST、CONTEST_LIST 和 JOB_LIST。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, BufReader};
+use std::path::Path;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::api::contests::Contest;
+use crate::api::jobs::JobResponse;
+use crate::api::users::User;
+use crate::{CONTEST_LIST, JOB_LIST, USER_LIST};
+
+lazy_static! {
+    /// 以追加方式打开的数据文件，未调用 `open` 时为 `None`，此时修改不会被保存
+    static ref DATA_FILE: Mutex<Option<File>> = Mutex::new(None);
+}
+
+/// 数据文件中的一条记录，同一 id 的后一条记录覆盖前一条
+#[derive(Serialize, Deserialize)]
+enum Record {
+    User(User),
+    Contest(Contest),
+    Job(JobResponse),
+}
+
+/// 打开数据文件并恢复其中保存的用户、比赛和评测任务。
+///
+/// `flush` 为真时先清空数据文件。恢复后数据文件会被压缩为每个对象一条记录。
+pub fn open(path: &Path, flush: bool) -> io::Result<()> {
+    if flush && path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    let mut users = USER_LIST.lock().unwrap();
+    let mut contests = CONTEST_LIST.lock().unwrap();
+    let mut jobs = JOB_LIST.lock().unwrap();
+
+    // # 重放数据文件
+    if path.exists() {
+        let reader = BufReader::new(File::open(path)?);
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(Record::User(user)) => upsert(&mut users, user, |a, b| a.id == b.id),
+                Ok(Record::Contest(contest)) => upsert(&mut contests, contest, |a, b| a.id == b.id),
+                Ok(Record::Job(job)) => upsert(&mut jobs, job, |a, b| a.id == b.id),
+                // 服务被强制结束时最后一行可能没有写完整
+                Err(err) => {
+                    log::warn!("skipping line {} of {}: {}", index + 1, path.display(), err)
+                }
+            }
+        }
+    }
+    users.sort_by_key(|x| x.id);
+    contests.sort_by_key(|x| x.id);
+    jobs.sort_by_key(|x| x.id);
+
+    // # 压缩数据文件
+    let tmp_path = path.with_extension("tmp");
+    let mut tmp_file = File::create(&tmp_path)?;
+    for user in users.iter() {
+        write_record(&mut tmp_file, &Record::User(user.clone()))?;
+    }
+    for contest in contests.iter() {
+        write_record(&mut tmp_file, &Record::Contest(contest.clone()))?;
+    }
+    for job in jobs.iter() {
+        write_record(&mut tmp_file, &Record::Job(job.clone()))?;
+    }
+    tmp_file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    let file = OpenOptions::new().append(true).open(path)?;
+    *DATA_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// 保存新建或修改后的用户
+pub fn save_user(user: &User) {
+    append(Record::User(user.clone()));
+}
+
+/// 保存新建的比赛
+pub fn save_contest(contest: &Contest) {
+    append(Record::Contest(contest.clone()));
+}
+
+/// 保存新建或状态变化后的评测任务
+pub fn save_job(job: &JobResponse) {
+    append(Record::Job(job.clone()));
+}
+
+fn append(record: Record) {
+    let mut lock = DATA_FILE.lock().unwrap();
+    if let Some(file) = lock.as_mut() {
+        if let Err(err) = write_record(file, &record) {
+            log::error!("failed to write data file: {}", err);
+        }
+    }
+}
+
+fn write_record(file: &mut File, record: &Record) -> io::Result<()> {
+    // 一次写入整行，避免被强制结束时留下与下一条记录相连的半行
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())
+}
+
+/// 用 `item` 替换 `list` 中与之相同的元素，不存在时追加到末尾
+fn upsert<T, F: Fn(&T, &T) -> bool>(list: &mut Vec<T>, item: T, same: F) {
+    match list.iter().position(|x| same(x, &item)) {
+        Some(index) => list[index] = item,
+        None => list.push(item),
+    }
+}
@@ -1,3 +1,11 @@
+// 模板提供的测试框架保持原样，不按照这些风格检查修改
+#![allow(
+    clippy::expect_fun_call,
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::single_component_path_imports
+)]
+
 use assert_json_diff::{assert_json_matches_no_panic, CompareMode, Config};
 use lazy_static::lazy_static;
 use reqwest::blocking::{Client, RequestBuilder};
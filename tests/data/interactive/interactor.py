@@ -0,0 +1,24 @@
+#!/usr/bin/env python3
+# 猜数交互程序：先向选手程序发送上界 n，选手每次输出一个猜测，
+# 交互程序回复 "<"（答案更小）、">"（答案更大）或 "="（猜中）
+import sys
+
+n = int(open(sys.argv[1], 'r').read().strip())
+answer = int(open(sys.argv[2], 'r').read().strip())
+max_guesses = n.bit_length() + 1
+
+print(n, flush=True)
+for guesses in range(1, max_guesses + 1):
+    line = sys.stdin.readline()
+    if not line:
+        print('Unexpected end of output', file=sys.stderr)
+        sys.exit(1)
+    guess = int(line.strip())
+    if guess == answer:
+        print('=', flush=True)
+        print(f'Found the answer in {guesses} guesses', file=sys.stderr)
+        sys.exit(0)
+    print('<' if answer < guess else '>', flush=True)
+
+print('Too many guesses', file=sys.stderr)
+sys.exit(1)